@@ -0,0 +1,324 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{Local, TimeZone};
+use serde::Serialize;
+use zip::ZipArchive;
+
+// file types of the unix mode of zip entries, as in `stat.h`
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+
+/// Longer symlink targets than this are not valid paths anyway
+const MAX_SYMLINK_TARGET: u64 = 4096;
+
+/// The formats a package can be published in, in order of preference
+pub const FORMATS: [Format; 3] = [Format::TarZst, Format::TarGz, Format::Zip];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Format {
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "tar.zst")]
+    TarZst,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Zip => ".zip",
+            Self::TarGz => ".tar.gz",
+            Self::TarZst => ".tar.zst",
+        }
+    }
+
+    /// Splits a file name like `7.0.1-stable.tar.gz` into `7.0.1-stable` and its format.
+    pub fn split(path: &str) -> Option<(&str, Self)> {
+        FORMATS
+            .into_iter()
+            .find_map(|format| Some((path.strip_suffix(format.extension())?, format)))
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next()?.trim();
+        match mime {
+            "application/zip" | "application/x-zip-compressed" => Some(Self::Zip),
+            "application/gzip" | "application/x-gzip" | "application/x-gtar" => Some(Self::TarGz),
+            "application/zstd" | "application/x-zstd" => Some(Self::TarZst),
+            _ => None,
+        }
+    }
+
+    /// Opens an archive of this format from `reader`, which is read once per pass over the entries.
+    pub fn open<'a, R: Read + Seek + 'a>(self, reader: R) -> io::Result<Box<dyn Archive + 'a>> {
+        Ok(match self {
+            Self::Zip => Box::new(ZipArchive::new(reader)?),
+            Self::TarGz | Self::TarZst => Box::new(TarArchive {
+                reader,
+                format: self,
+            }),
+        })
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.extension()[1..])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    /// Devices, FIFOs, sockets and hard links
+    Other,
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    /// The path as stored in the archive, see [`enclosed_name`]
+    pub name: String,
+    pub kind: EntryKind,
+    /// The permissions, including the setuid, setgid and sticky bits
+    pub mode: Option<u32>,
+    pub size: u64,
+    /// Only known for formats compressing each entry on its own
+    pub compressed_size: Option<u64>,
+    /// The CRC-32 of the content, only stored by zip
+    pub crc32: Option<u32>,
+    pub mtime: Option<SystemTime>,
+    /// Where a symlink entry points to, as stored in the archive
+    pub target: Option<String>,
+}
+
+/// A package archive, whatever its format
+pub trait Archive {
+    /// Lists the entries from the archive metadata, without extracting them.
+    fn entries(&mut self) -> io::Result<Vec<Entry>>;
+
+    /// Calls `visit` for every entry, in archive order, with a reader over its content.
+    fn visit(
+        &mut self,
+        visit: &mut dyn FnMut(&Entry, &mut dyn Read) -> io::Result<()>,
+    ) -> io::Result<()>;
+}
+
+impl<R: Read + Seek> Archive for ZipArchive<R> {
+    fn entries(&mut self) -> io::Result<Vec<Entry>> {
+        let mut entries = Vec::with_capacity(self.len());
+        for i in 0..self.len() {
+            let mut entry = zip_entry(&self.by_index_raw(i)?);
+            // the target of a symlink is its content, small enough to be read along with the metadata
+            if entry.kind == EntryKind::Symlink {
+                entry.target = Some(read_target(&mut self.by_index(i)?)?);
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    fn visit(
+        &mut self,
+        visit: &mut dyn FnMut(&Entry, &mut dyn Read) -> io::Result<()>,
+    ) -> io::Result<()> {
+        for i in 0..self.len() {
+            let mut file = self.by_index(i)?;
+            let mut entry = zip_entry(&file);
+            if entry.kind == EntryKind::Symlink {
+                entry.target = Some(read_target(&mut file)?);
+            }
+            visit(&entry, &mut file)?;
+        }
+        Ok(())
+    }
+}
+
+fn zip_entry(file: &zip::read::ZipFile<'_>) -> Entry {
+    let mode = file.unix_mode();
+    let kind = match mode.map(|mode| mode & S_IFMT) {
+        _ if file.is_dir() => EntryKind::Dir,
+        None | Some(0 | S_IFREG) => EntryKind::File,
+        Some(S_IFDIR) => EntryKind::Dir,
+        Some(S_IFLNK) => EntryKind::Symlink,
+        Some(_) => EntryKind::Other,
+    };
+    Entry {
+        name: file.name().to_owned(),
+        kind,
+        mode: mode.map(|mode| mode & 0o7777),
+        size: file.size(),
+        compressed_size: Some(file.compressed_size()),
+        crc32: Some(file.crc32()),
+        mtime: zip_mtime(file.last_modified()),
+        target: None,
+    }
+}
+
+fn read_target(content: &mut impl Read) -> io::Result<String> {
+    let mut target = String::new();
+    content
+        .take(MAX_SYMLINK_TARGET)
+        .read_to_string(&mut target)?;
+    Ok(target)
+}
+
+/// Zip stores the local time of the system the archive was made on, assumed to be ours
+fn zip_mtime(datetime: zip::DateTime) -> Option<SystemTime> {
+    let datetime = Local
+        .with_ymd_and_hms(
+            datetime.year().into(),
+            datetime.month().into(),
+            datetime.day().into(),
+            datetime.hour().into(),
+            datetime.minute().into(),
+            datetime.second().into(),
+        )
+        .earliest()?;
+    Some(datetime.into())
+}
+
+/// A compressed tarball, decompressed again for each pass
+struct TarArchive<R> {
+    reader: R,
+    format: Format,
+}
+
+impl<R: Read + Seek> TarArchive<R> {
+    fn for_each(
+        &mut self,
+        mut f: impl FnMut(Entry, &mut tar::Entry<'_, Box<dyn Read + '_>>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(0))?;
+        let decoder: Box<dyn Read + '_> = match self.format {
+            Format::TarGz => Box::new(flate2::read::GzDecoder::new(&mut self.reader)),
+            Format::TarZst => Box::new(zstd::Decoder::new(&mut self.reader)?),
+            Format::Zip => unreachable!("zip archives are not tarballs"),
+        };
+        let mut archive = tar::Archive::new(decoder);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let header = entry.header();
+            let kind = match header.entry_type() {
+                tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+                tar::EntryType::Directory => EntryKind::Dir,
+                tar::EntryType::Symlink => EntryKind::Symlink,
+                // metadata of the archive itself, not an entry
+                tar::EntryType::XGlobalHeader => continue,
+                _ => EntryKind::Other,
+            };
+            let target = match kind {
+                EntryKind::Symlink => entry
+                    .link_name_bytes()
+                    .map(|target| String::from_utf8_lossy(&target).into_owned()),
+                _ => None,
+            };
+            let size = match &target {
+                Some(target) => target.len() as u64,
+                None => entry.size(),
+            };
+            let meta = Entry {
+                name: String::from_utf8_lossy(&entry.path_bytes()).into_owned(),
+                kind,
+                mode: header.mode().ok().map(|mode| mode & 0o7777),
+                size,
+                compressed_size: None,
+                crc32: None,
+                mtime: header
+                    .mtime()
+                    .ok()
+                    .map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime)),
+                target,
+            };
+            f(meta, &mut entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Archive for TarArchive<R> {
+    fn entries(&mut self) -> io::Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        self.for_each(|entry, _| {
+            entries.push(entry);
+            Ok(())
+        })?;
+        Ok(entries)
+    }
+
+    fn visit(
+        &mut self,
+        visit: &mut dyn FnMut(&Entry, &mut dyn Read) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.for_each(|entry, content| visit(&entry, content))
+    }
+}
+
+/// Why an archive is rejected, carried by an [`io::Error`] of kind `InvalidData`
+#[derive(Debug)]
+pub struct InvalidArchive(pub String);
+
+impl std::fmt::Display for InvalidArchive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidArchive {}
+
+impl From<InvalidArchive> for io::Error {
+    fn from(err: InvalidArchive) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// The relative path of an entry, `None` when it could be written outside of the installation directory.
+///
+/// `..` is rejected even when it seems to stay inside, as it would go back through a symlink of the archive.
+pub fn enclosed_name(name: &str) -> Option<PathBuf> {
+    if name.contains('\0') {
+        return None;
+    }
+    let path = Path::new(name);
+    for component in path.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => (),
+            Component::Prefix(_) | Component::RootDir | Component::ParentDir => return None,
+        }
+    }
+    Some(path.to_path_buf())
+}
+
+/// Whether the symlink entry `link`, as returned by [`enclosed_name`], points inside the installation directory.
+///
+/// `..` is only accepted at the start of `target` and no further up than the directory of `link`, the symlinks
+/// `target` may go through further down being checked on their own.
+pub fn symlink_enclosed(link: &Path, target: &str) -> bool {
+    if target.contains('\0') {
+        return false;
+    }
+    let mut depth = link
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .count()
+        .saturating_sub(1);
+    let mut descended = false;
+    for component in Path::new(target).components() {
+        match component {
+            Component::ParentDir if !descended => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            Component::CurDir => (),
+            Component::Normal(_) => descended = true,
+            _ => return false,
+        }
+    }
+    true
+}
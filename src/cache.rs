@@ -0,0 +1,46 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+/// A value cached on disk in the `cache/` of an installation directory, along with when it was computed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cached<T> {
+    pub updated_at: DateTime<Local>,
+    pub value: T,
+}
+
+impl<T> Cached<T> {
+    pub fn is_fresh(&self, ttl: chrono::Duration) -> bool {
+        Local::now() - self.updated_at < ttl
+    }
+}
+
+/// The cache of the installation in `home`, shared with its toolchains
+pub fn dir(home: &Path) -> PathBuf {
+    home.join("cache")
+}
+
+/// Returns `None` when there is no (valid) cache entry for `name`.
+pub fn read<T: DeserializeOwned>(home: &Path, name: &str) -> Option<Cached<T>> {
+    let content = fs::read(dir(home).join(name)).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+pub fn write<T: Serialize>(home: &Path, name: &str, value: T) -> Result<()> {
+    let dir = dir(home);
+    fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let cached = Cached {
+        updated_at: Local::now(),
+        value,
+    };
+    let path = dir.join(name);
+    // write then rename, so that concurrent gcm invocations never read a partial entry
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp, serde_json::to_vec(&cached)?)
+        .with_context(|| format!("writing {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
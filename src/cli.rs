@@ -0,0 +1,122 @@
+//! The `gcm` command line, the binary only calling [`main`].
+
+use std::path::Path;
+
+use anyhow::Result;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use clap_complete::CompleteEnv;
+
+use crate::cmd::*;
+use crate::notice;
+use crate::reporter::{ColorMode, Reporter};
+use crate::shim;
+use crate::toolchain;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = ColorMode::Auto,
+        help = "When to use colors, 'auto' disables them when NO_COLOR is set or stderr is not a terminal"
+    )]
+    color: ColorMode,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        conflicts_with = "verbose",
+        help = "Do not display progress and status messages"
+    )]
+    quiet: bool,
+
+    #[arg(short, long, global = true, action = ArgAction::Count, help = "Logs verbosity")]
+    verbose: u8,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    List(List),
+    Install(Install),
+    Update(Update),
+    Uninstall(Uninstall),
+    Show(Show),
+    Search(Search),
+    Outdated(Outdated),
+    Doctor(Doctor),
+    Env(Env),
+    InitShell(InitShell),
+    Completions(Completions),
+    Run(Run),
+    #[command(name = "self")]
+    SelfManage(SelfManage),
+}
+
+pub fn main() -> Result<()> {
+    if let Some((name, home)) = shim::invoked_as() {
+        return run_shim(&name, &home);
+    }
+    CompleteEnv::with_factory(Cli::command)
+        .var(COMPLETE_VAR)
+        .complete();
+    env_logger::init();
+    let cli = Cli::parse();
+    let reporter = Reporter::new(cli.color, cli.quiet, cli.verbose);
+
+    let update_check = !matches!(
+        cli.command,
+        Command::SelfManage(_)
+            | Command::Outdated(_)
+            | Command::Doctor(_)
+            // meant to be evaluated by the shell
+            | Command::Env(_)
+            | Command::InitShell(_)
+            | Command::Completions(_)
+            // replaced by the command it runs, checking right before
+            | Command::Run(_)
+            // meant to change nothing, the cache of the check included
+            | Command::Install(Install { dry_run: true, .. })
+            | Command::Update(Update { dry_run: true, .. })
+            | Command::Uninstall(Uninstall { dry_run: true, .. })
+    );
+    if update_check {
+        notice::spawn_check();
+    }
+
+    let res = match cli.command {
+        Command::List(cmd) => cmd.run(&reporter),
+        Command::Install(cmd) => cmd.run(&reporter),
+        Command::Update(cmd) => cmd.run(&reporter),
+        Command::Uninstall(cmd) => cmd.run(&reporter),
+        Command::Show(cmd) => cmd.run(&reporter),
+        Command::Search(cmd) => cmd.run(&reporter),
+        Command::Outdated(cmd) => cmd.run(&reporter),
+        Command::Doctor(cmd) => cmd.run(&reporter),
+        Command::Env(cmd) => cmd.run(&reporter),
+        Command::InitShell(cmd) => cmd.run(&reporter),
+        Command::Completions(cmd) => cmd.run(&reporter),
+        Command::Run(cmd) => cmd.run(&reporter),
+        Command::SelfManage(cmd) => cmd.run(&reporter),
+    };
+
+    if update_check && res.is_ok() {
+        notice::print_hints(&reporter);
+    }
+
+    res
+}
+
+/// Runs `name` from the active toolchain of `home`, gcm having been invoked through its shim.
+fn run_shim(name: &str, home: &Path) -> Result<()> {
+    let reporter = Reporter::new(ColorMode::Auto, false, 0);
+    let toolchain = toolchain::active(home)?.map(|(toolchain, _)| toolchain);
+    let dir = toolchain_dir(home, toolchain, &reporter)?;
+    notice::check_before_exec(&reporter);
+    shim::run(name, &dir)
+}
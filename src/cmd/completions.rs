@@ -0,0 +1,152 @@
+use std::ffi::OsStr;
+use std::io;
+
+use anyhow::Result;
+use clap::{CommandFactory, Parser, ValueEnum};
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
+
+use crate::index;
+use crate::install::install_dir;
+use crate::receipt::Receipt;
+use crate::reporter::Reporter;
+
+/// The environment variable the completion scripts set when calling back into gcm
+pub const COMPLETE_VAR: &str = "GCM_COMPLETE";
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Shell {
+    Bash,
+    Elvish,
+    Fish,
+    Powershell,
+    Zsh,
+}
+
+#[derive(Debug, Parser)]
+#[clap(about = "Prints the completion script of a shell\neg. source <(gcm completions bash)")]
+pub struct Completions {
+    #[arg(value_enum, help = "The shell to complete for")]
+    shell: Shell,
+}
+
+impl Completions {
+    pub fn run(self, _reporter: &Reporter) -> Result<()> {
+        let value = self.shell.to_possible_value().unwrap();
+        let shells = Shells::builtins();
+        let shell = shells.completer(value.get_name()).unwrap();
+        // the script calls back into gcm to complete package names and branches
+        let completer = std::env::current_exe()
+            .map(|exe| exe.display().to_string())
+            .unwrap_or_else(|_| "gcm".to_string());
+        shell.write_registration(COMPLETE_VAR, "gcm", "gcm", &completer, &mut io::stdout())?;
+        Ok(())
+    }
+}
+
+/// `gcm install [branch]` or `gcm install [package] [branch]`
+pub fn complete_install_args(current: &OsStr) -> Vec<CompletionCandidate> {
+    match preceding_args().as_slice() {
+        [] => {
+            let mut values = index::branches("core");
+            values.extend(index::packages());
+            values.sort();
+            values.dedup();
+            candidates(current, values)
+        }
+        [package] => candidates(current, index::branches(package)),
+        _ => Vec::new(),
+    }
+}
+
+pub fn complete_packages(current: &OsStr) -> Vec<CompletionCandidate> {
+    candidates(current, index::packages())
+}
+
+/// The branches of the package given as previous argument
+pub fn complete_branches(current: &OsStr) -> Vec<CompletionCandidate> {
+    match preceding_args().first() {
+        Some(package) => candidates(current, index::branches(package)),
+        None => Vec::new(),
+    }
+}
+
+/// `<package>[@branch]`
+pub fn complete_package_spec(current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+    match current.split_once('@') {
+        Some((package, _)) => {
+            let values = index::branches(package)
+                .into_iter()
+                .map(|branch| format!("{package}@{branch}"))
+                .collect();
+            candidates(OsStr::new(current.as_ref()), values)
+        }
+        None => candidates(OsStr::new(current.as_ref()), index::packages()),
+    }
+}
+
+pub fn complete_installed(current: &OsStr) -> Vec<CompletionCandidate> {
+    let installed = match Receipt::load(&install_dir(None)) {
+        Ok(Some(receipt)) => receipt.packages.into_iter().map(|p| p.name).collect(),
+        _ => Vec::new(),
+    };
+    candidates(current, installed)
+}
+
+fn candidates(current: &OsStr, values: Vec<String>) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+    values
+        .into_iter()
+        .filter(|value| value.starts_with(current.as_ref()))
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// The positional arguments of the subcommand being completed that come before the word under the cursor.
+///
+/// Value completers only get the current word, the whole command line is read back from the arguments gcm was
+/// called with by the completion script: `gcm -- gcm <words>`.
+fn preceding_args() -> Vec<String> {
+    let words: Vec<String> = std::env::args()
+        .skip_while(|arg| arg != "--")
+        .skip(1)
+        .collect();
+    // only some shells tell which word is under the cursor, the others cut the line at the cursor
+    let cursor = std::env::var("_CLAP_COMPLETE_INDEX")
+        .ok()
+        .and_then(|index| index.parse().ok())
+        .unwrap_or(words.len().saturating_sub(1));
+    let words = words.get(1..cursor).unwrap_or_default();
+
+    let mut cli = crate::cli::Cli::command();
+    cli.build();
+    let mut cmd = &cli;
+    let mut args = Vec::new();
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        let arg = if let Some(long) = word.strip_prefix("--") {
+            cmd.get_arguments().find(|arg| arg.get_long() == Some(long))
+        } else if let Some(short) = word.strip_prefix('-')
+            && let [short] = short.chars().collect::<Vec<_>>()[..]
+        {
+            cmd.get_arguments()
+                .find(|arg| arg.get_short() == Some(short))
+        } else if word.starts_with('-') {
+            continue;
+        } else if args.is_empty()
+            && let Some(subcommand) = cmd.find_subcommand(word)
+        {
+            cmd = subcommand;
+            continue;
+        } else {
+            args.push(word.clone());
+            continue;
+        };
+        // skips the value of options like `--dir <DIR>`
+        if arg.is_some_and(|arg| arg.get_action().takes_values()) {
+            words.next();
+        }
+    }
+    args
+}
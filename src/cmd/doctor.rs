@@ -0,0 +1,489 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{Result, bail};
+use clap::Parser;
+use termcolor::Color;
+
+use crate::install::{get_arch, install_dir};
+use crate::progress::FmtBytes;
+use crate::receipt::Receipt;
+use crate::registry::Registry;
+use crate::reporter::Reporter;
+use crate::shim::shims_dir;
+use crate::toolchain;
+
+/// Below that, installing or updating GreyCat is likely to fail midway
+const LOW_DISK_SPACE: u64 = 500 * 1024 * 1024;
+
+const PROXY_VARS: [&str; 6] = [
+    "ALL_PROXY",
+    "all_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+];
+
+#[derive(Debug, Parser)]
+#[clap(
+    about = "Diagnoses the local GreyCat setup and suggests fixes\nExits with a non-zero status when a check fails"
+)]
+pub struct Doctor {
+    #[arg(
+        long,
+        help = "The installation directory, defaults to $GREYCAT_HOME or $HOME/.greycat"
+    )]
+    dir: Option<PathBuf>,
+
+    #[arg(long, help = "Skips the checks that need network access")]
+    offline: bool,
+}
+
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    message: String,
+    fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Warn,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(name: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Fail,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+impl Doctor {
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        let dir = install_dir(self.dir);
+        let receipt = Receipt::load(&dir);
+
+        let mut checks = vec![
+            check_dir(&dir),
+            check_receipt(&dir, &receipt),
+            check_path(&dir),
+            check_toolchain(&dir),
+            check_arch(&dir, receipt.as_ref().ok().and_then(Option::as_ref)),
+            check_permissions(&dir),
+            check_disk_space(&dir),
+            check_proxy(),
+            check_tls(),
+        ];
+        if !self.offline {
+            checks.push(check_registry());
+        }
+
+        let mut failed = 0;
+        for check in &checks {
+            let (color, label) = match check.status {
+                Status::Ok => (Color::Green, "ok"),
+                Status::Warn => (Color::Yellow, "warn"),
+                Status::Fail => {
+                    failed += 1;
+                    (Color::Red, "fail")
+                }
+            };
+            reporter.check(
+                color,
+                label,
+                format_args!("{:12} {}", check.name, check.message),
+            );
+            if let Some(fix) = &check.fix {
+                println!("      {:12} fix: {fix}", "");
+            }
+        }
+
+        if failed > 0 {
+            bail!("{failed} check(s) failed");
+        }
+        Ok(())
+    }
+}
+
+fn check_dir(dir: &Path) -> Check {
+    const NAME: &str = "directory";
+    if !dir.is_dir() {
+        return Check::fail(
+            NAME,
+            format!("{} does not exist", dir.display()),
+            "run 'gcm install', or point $GREYCAT_HOME to an existing installation",
+        );
+    }
+    let probe = dir.join(".gcm-doctor");
+    match fs::write(&probe, b"") {
+        Ok(()) => {
+            fs::remove_file(&probe).ok();
+            Check::ok(NAME, format!("{} is writable", dir.display()))
+        }
+        Err(err) => Check::fail(
+            NAME,
+            format!("{} is not writable: {err}", dir.display()),
+            format!("fix the ownership of {} or use --dir", dir.display()),
+        ),
+    }
+}
+
+fn check_receipt(dir: &Path, receipt: &Result<Option<Receipt>>) -> Check {
+    const NAME: &str = "receipt";
+    let receipt = match receipt {
+        Ok(Some(receipt)) => receipt,
+        Ok(None) if greycat_bin(dir).is_file() => {
+            return Check::warn(
+                NAME,
+                "GreyCat was installed without receipt, gcm cannot tell which files it owns",
+                "run 'gcm update' to reinstall and record it",
+            );
+        }
+        Ok(None) => {
+            return Check::fail(
+                NAME,
+                "nothing installed",
+                "run 'gcm install' to install the latest 'stable'",
+            );
+        }
+        Err(err) => {
+            return Check::fail(
+                NAME,
+                format!("{err:#}"),
+                format!(
+                    "remove {} and run 'gcm update'",
+                    Receipt::path(dir).display()
+                ),
+            );
+        }
+    };
+
+    let broken: Vec<_> = receipt
+        .packages
+        .iter()
+        .filter_map(|installed| {
+            let missing = installed
+                .files
+                .iter()
+                .filter(|file| !dir.join(file).exists())
+                .count();
+            (missing > 0).then_some((installed, missing))
+        })
+        .collect();
+    if broken.is_empty() {
+        let packages = receipt
+            .packages
+            .iter()
+            .map(|p| format!("{}@{} {}", p.name, p.branch, p.version))
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Check::ok(NAME, format!("all recorded files present ({packages})"));
+    }
+
+    let message = broken
+        .iter()
+        .map(|(installed, missing)| format!("{} is missing {missing} file(s)", installed.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let fix = broken
+        .iter()
+        .map(|(installed, _)| format!("'gcm install {} {}'", installed.name, installed.version))
+        .collect::<Vec<_>>()
+        .join(" and ");
+    Check::fail(NAME, message, format!("reinstall with {fix}"))
+}
+
+fn check_path(dir: &Path) -> Check {
+    const NAME: &str = "PATH";
+    // with shims, the toolchain is selected per project
+    let bin = match shims_dir(dir) {
+        shims if shims.is_dir() => shims,
+        _ => dir.join("bin"),
+    };
+    let expected = bin.join(format!("greycat{}", std::env::consts::EXE_SUFFIX));
+    let found = std::env::var_os("PATH").and_then(|path| {
+        std::env::split_paths(&path)
+            .map(|p| p.join(expected.file_name().unwrap()))
+            .find(|p| p.is_file())
+    });
+    match found {
+        None => Check::warn(
+            NAME,
+            "no greycat found on PATH",
+            format!(
+                "run 'gcm init-shell', or add {} to your PATH",
+                bin.display()
+            ),
+        ),
+        Some(found) if same_file(&found, &expected) => {
+            Check::ok(NAME, format!("greycat resolves to {}", found.display()))
+        }
+        Some(found) => Check::warn(
+            NAME,
+            format!(
+                "greycat resolves to {}, not {}",
+                found.display(),
+                expected.display()
+            ),
+            format!(
+                "remove {} or move {} earlier in your PATH",
+                found.display(),
+                bin.display()
+            ),
+        ),
+    }
+}
+
+fn check_toolchain(dir: &Path) -> Check {
+    const NAME: &str = "toolchain";
+    match toolchain::active(dir) {
+        Ok(Some((toolchain, source))) => Check::ok(NAME, format!("{toolchain} (from {source})")),
+        Ok(None) => Check::ok(NAME, "default installation"),
+        Err(err) => Check::fail(
+            NAME,
+            format!("{err:#}"),
+            "fix the toolchain, eg. 'dev' or '7.0', in $GREYCAT_TOOLCHAIN, .greycat-toolchain or config.json",
+        ),
+    }
+}
+
+fn check_arch(dir: &Path, receipt: Option<&Receipt>) -> Check {
+    const NAME: &str = "arch";
+    let arch = get_arch();
+    if let Some(core) = receipt.and_then(|receipt| receipt.get("core"))
+        && let Some(installed) = &core.arch
+        && *installed != arch
+    {
+        return Check::fail(
+            NAME,
+            format!("core was installed for {installed}, this machine is {arch}"),
+            format!("run 'gcm install core {} --arch {arch}'", core.branch),
+        );
+    }
+    let greycat = greycat_bin(dir);
+    match binary_arch(&greycat) {
+        Some(binary) if binary != arch => Check::fail(
+            NAME,
+            format!(
+                "{} is a {binary} binary, this machine is {arch}",
+                greycat.display()
+            ),
+            format!("run 'gcm install --arch {arch}'"),
+        ),
+        Some(_) => Check::ok(NAME, arch),
+        None if greycat.is_file() => Check::warn(
+            NAME,
+            format!("unable to tell the architecture of {}", greycat.display()),
+            format!("reinstall with 'gcm install --arch {arch}'"),
+        ),
+        None => Check::ok(NAME, format!("{arch}, greycat not installed")),
+    }
+}
+
+#[cfg(unix)]
+fn check_permissions(dir: &Path) -> Check {
+    use std::os::unix::fs::PermissionsExt;
+
+    const NAME: &str = "permissions";
+    let bin = dir.join("bin");
+    let Ok(entries) = fs::read_dir(&bin) else {
+        return Check::ok(NAME, format!("no {}", bin.display()));
+    };
+    let not_executable: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .metadata()
+                .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 == 0)
+        })
+        .map(|entry| entry.path())
+        .collect();
+    if not_executable.is_empty() {
+        return Check::ok(NAME, format!("{} is executable", bin.display()));
+    }
+    let files = not_executable
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Check::fail(
+        NAME,
+        format!("{} file(s) are not executable", not_executable.len()),
+        format!("chmod +x {files}"),
+    )
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_dir: &Path) -> Check {
+    Check::ok("permissions", "not checked on this platform")
+}
+
+#[cfg(unix)]
+fn check_disk_space(dir: &Path) -> Check {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    const NAME: &str = "disk space";
+    // the installation directory may not exist yet
+    let Some(existing) = dir.ancestors().find(|p| p.exists()) else {
+        return Check::ok(NAME, "not checked");
+    };
+    let Ok(path) = CString::new(existing.as_os_str().as_bytes()) else {
+        return Check::ok(NAME, "not checked");
+    };
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string and `stat` is only read when statvfs succeeded
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Check::warn(
+            NAME,
+            format!(
+                "unable to stat {}: {}",
+                existing.display(),
+                std::io::Error::last_os_error()
+            ),
+            "check that the filesystem is mounted",
+        );
+    }
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)]
+    let available = stat.f_bavail as u64 * stat.f_frsize as u64;
+    if available < LOW_DISK_SPACE {
+        return Check::warn(
+            NAME,
+            format!("only {} available", FmtBytes(available)),
+            format!(
+                "free up space on the filesystem holding {}",
+                existing.display()
+            ),
+        );
+    }
+    Check::ok(NAME, format!("{} available", FmtBytes(available)))
+}
+
+#[cfg(not(unix))]
+fn check_disk_space(_dir: &Path) -> Check {
+    Check::ok("disk space", "not checked on this platform")
+}
+
+fn check_proxy() -> Check {
+    const NAME: &str = "proxy";
+    let Some((var, proxy)) = PROXY_VARS
+        .iter()
+        .find_map(|var| Some((var, std::env::var(var).ok().filter(|v| !v.is_empty())?)))
+    else {
+        return Check::ok(NAME, "none");
+    };
+    match ureq::Proxy::new(&proxy) {
+        Ok(_) => Check::ok(NAME, format!("{proxy} (from ${var})")),
+        Err(err) => Check::fail(
+            NAME,
+            format!("invalid ${var} {proxy:?}: {err}"),
+            format!("set ${var} to eg. http://host:port, or unset it"),
+        ),
+    }
+}
+
+fn check_tls() -> Check {
+    const NAME: &str = "tls";
+    let custom = ["SSL_CERT_FILE", "SSL_CERT_DIR"]
+        .into_iter()
+        .find(|var| std::env::var_os(var).is_some_and(|v| !v.is_empty()));
+    match custom {
+        Some(var) => Check::warn(
+            NAME,
+            format!("${var} is set but gcm only trusts its bundled Mozilla root certificates"),
+            "if the registry check fails, your network may intercept TLS: ask for get.greycat.io to be excluded",
+        ),
+        None => Check::ok(NAME, "bundled Mozilla root certificates"),
+    }
+}
+
+fn check_registry() -> Check {
+    const NAME: &str = "registry";
+    let start = Instant::now();
+    match Registry::default().list_files("") {
+        Ok(_) => Check::ok(
+            NAME,
+            format!("reachable in {}ms", start.elapsed().as_millis()),
+        ),
+        Err(err) => Check::fail(
+            NAME,
+            format!("{err:#}"),
+            "check your network connection, firewall and proxy settings for https://get.greycat.io",
+        ),
+    }
+}
+
+fn greycat_bin(dir: &Path) -> PathBuf {
+    dir.join("bin")
+        .join(format!("greycat{}", std::env::consts::EXE_SUFFIX))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Reads the target of an executable from its ELF, Mach-O or PE header, in the `get_arch` naming.
+fn binary_arch(path: &Path) -> Option<&'static str> {
+    let mut bytes = Vec::new();
+    fs::File::open(path)
+        .ok()?
+        .take(4096)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    let u16_le = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+    let u32_le = |at: usize| Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+
+    if bytes.starts_with(b"\x7fELF") {
+        return match u16_le(18)? {
+            0x3e => Some("x64-linux"),
+            0xb7 => Some("arm64-linux"),
+            _ => None,
+        };
+    }
+    if bytes.starts_with(&[0xcf, 0xfa, 0xed, 0xfe]) {
+        return match u32_le(4)? {
+            0x0100_0007 => Some("x64-apple"),
+            0x0100_000c => Some("arm64-apple"),
+            _ => None,
+        };
+    }
+    if bytes.starts_with(b"MZ") {
+        let pe = u32_le(0x3c)? as usize;
+        return match u16_le(pe + 4)? {
+            0x8664 => Some("x64-windows"),
+            0xaa64 => Some("arm64-windows"),
+            _ => None,
+        };
+    }
+    None
+}
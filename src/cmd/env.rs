@@ -0,0 +1,214 @@
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::{Parser, ValueEnum};
+use termcolor::Color;
+
+use crate::install::install_dir;
+use crate::reporter::Reporter;
+use crate::shim::shims_dir;
+
+const BLOCK_START: &str = "# >>> gcm >>>";
+const BLOCK_END: &str = "# <<< gcm <<<";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+#[derive(Debug, Parser)]
+#[clap(
+    about = "Prints the shell commands setting GREYCAT_HOME and adding its bin/ (or shims/) to PATH\neg. eval \"$(gcm env)\""
+)]
+pub struct Env {
+    #[arg(long, value_enum, help = "The shell to print for, defaults to $SHELL")]
+    shell: Option<Shell>,
+
+    #[arg(
+        long,
+        help = "The installation directory, defaults to $GREYCAT_HOME or $HOME/.greycat"
+    )]
+    dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+#[clap(
+    about = "Adds the output of 'gcm env' to the rc file of your shell\nRunning it again replaces the previously added lines"
+)]
+pub struct InitShell {
+    #[arg(long, value_enum, help = "The shell to configure, defaults to $SHELL")]
+    shell: Option<Shell>,
+
+    #[arg(
+        long,
+        help = "The installation directory, defaults to $GREYCAT_HOME or $HOME/.greycat"
+    )]
+    dir: Option<PathBuf>,
+
+    #[arg(long, help = "The file to edit instead of the rc file of the shell")]
+    rc_file: Option<PathBuf>,
+}
+
+impl Env {
+    pub fn run(self, _reporter: &Reporter) -> Result<()> {
+        let shell = detect_shell(self.shell)?;
+        print!("{}", env_script(shell, &install_dir(self.dir)));
+        Ok(())
+    }
+}
+
+impl InitShell {
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        let shell = detect_shell(self.shell)?;
+        let rc_file = match self.rc_file {
+            Some(rc_file) => rc_file,
+            None => rc_file(shell)?,
+        };
+        let script = env_script(shell, &install_dir(self.dir));
+        let block = format!("{BLOCK_START}\n{script}{BLOCK_END}\n");
+
+        let content = match fs::read_to_string(&rc_file) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("reading {}", rc_file.display()));
+            }
+        };
+        let updated = match find_block(&content) {
+            Some(range) => format!(
+                "{}{block}{}",
+                &content[..range.start],
+                &content[range.end..]
+            ),
+            _ if content.is_empty() => block,
+            _ if content.ends_with('\n') => format!("{content}\n{block}"),
+            _ => format!("{content}\n\n{block}"),
+        };
+
+        if updated == content {
+            reporter.info(format_args!("{} is already up to date", rc_file.display()));
+            return Ok(());
+        }
+        if let Some(parent) = rc_file.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        fs::write(&rc_file, updated).with_context(|| format!("writing {}", rc_file.display()))?;
+
+        reporter.status(format_args!("updated {} ", rc_file.display()));
+        reporter.done(Color::Green, "done");
+        reporter.note("open a new shell for the changes to take effect");
+        Ok(())
+    }
+}
+
+/// Removes the lines added by [`InitShell`] from the rc file of every shell, returning the files changed.
+pub fn remove_init_shell() -> Result<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+    for shell in Shell::value_variants() {
+        let rc_file = rc_file(*shell)?;
+        let Ok(content) = fs::read_to_string(&rc_file) else {
+            continue;
+        };
+        let Some(range) = find_block(&content) else {
+            continue;
+        };
+        let updated = format!("{}{}", &content[..range.start], &content[range.end..]);
+        // the fish file is ours alone
+        let res = if *shell == Shell::Fish && updated.trim().is_empty() {
+            fs::remove_file(&rc_file)
+        } else {
+            fs::write(&rc_file, updated)
+        };
+        res.with_context(|| format!("writing {}", rc_file.display()))?;
+        changed.push(rc_file);
+    }
+    Ok(changed)
+}
+
+/// Where the lines added by [`InitShell`] are in `content`, the newline ending them included
+fn find_block(content: &str) -> Option<Range<usize>> {
+    let start = content.find(BLOCK_START)?;
+    let end = start + content[start..].find(BLOCK_END)?;
+    let end = content[end..]
+        .find('\n')
+        .map_or(content.len(), |n| end + n + 1);
+    Some(start..end)
+}
+
+fn detect_shell(shell: Option<Shell>) -> Result<Shell> {
+    if let Some(shell) = shell {
+        return Ok(shell);
+    }
+    if cfg!(target_os = "windows") {
+        return Ok(Shell::Powershell);
+    }
+    let var = std::env::var("SHELL").unwrap_or_default();
+    let name = Path::new(&var)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    match name {
+        "bash" | "sh" => Ok(Shell::Bash),
+        "zsh" => Ok(Shell::Zsh),
+        "fish" => Ok(Shell::Fish),
+        "pwsh" | "powershell" => Ok(Shell::Powershell),
+        _ => bail!("unable to detect your shell from $SHELL={var:?}, use --shell"),
+    }
+}
+
+/// The rc file sourced by every interactive instance of `shell`
+fn rc_file(shell: Shell) -> Result<PathBuf> {
+    let home = home::home_dir().context("unable to locate your home directory")?;
+    let config = || {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(|| home.join(".config"), PathBuf::from)
+    };
+    Ok(match shell {
+        Shell::Bash => home.join(".bashrc"),
+        Shell::Zsh => std::env::var_os("ZDOTDIR")
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(|| home.clone(), PathBuf::from)
+            .join(".zshrc"),
+        // fish sources every file of conf.d/, no need to edit config.fish
+        Shell::Fish => config().join("fish").join("conf.d").join("gcm.fish"),
+        Shell::Powershell if cfg!(target_os = "windows") => home
+            .join("Documents")
+            .join("PowerShell")
+            .join("Microsoft.PowerShell_profile.ps1"),
+        Shell::Powershell => config()
+            .join("powershell")
+            .join("Microsoft.PowerShell_profile.ps1"),
+    })
+}
+
+/// Sets `GREYCAT_HOME` to `dir` and prepends its `bin/` to `PATH`, unless already there.
+///
+/// Once gcm created shims, they replace `bin/` so that every project runs its own toolchain.
+fn env_script(shell: Shell, dir: &Path) -> String {
+    let bin = if shims_dir(dir).is_dir() {
+        "shims"
+    } else {
+        "bin"
+    };
+    let dir = dir.display().to_string();
+    match shell {
+        Shell::Bash | Shell::Zsh => format!(
+            "export GREYCAT_HOME='{}'\ncase \":$PATH:\" in\n  *\":$GREYCAT_HOME/{bin}:\"*) ;;\n  *) export PATH=\"$GREYCAT_HOME/{bin}:$PATH\" ;;\nesac\n",
+            dir.replace('\'', r"'\''")
+        ),
+        Shell::Fish => format!(
+            "set -gx GREYCAT_HOME '{}'\ncontains -- \"$GREYCAT_HOME/{bin}\" $PATH; or set -gx PATH \"$GREYCAT_HOME/{bin}\" $PATH\n",
+            dir.replace('\\', r"\\").replace('\'', r"\'")
+        ),
+        Shell::Powershell => format!(
+            "$env:GREYCAT_HOME = '{}'\n$greycatBin = Join-Path $env:GREYCAT_HOME '{bin}'\nif (($env:PATH -split [IO.Path]::PathSeparator) -notcontains $greycatBin) {{\n  $env:PATH = $greycatBin + [IO.Path]::PathSeparator + $env:PATH\n}}\n",
+            dir.replace('\'', "''")
+        ),
+    }
+}
@@ -1,15 +1,26 @@
-use std::{fs, path::PathBuf};
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use clap::Parser;
+use clap_complete::engine::ArgValueCompleter;
 use semver::Version;
 
+use crate::cmd::complete_install_args;
+use crate::install::{
+    Target, default_targets, get_arch, install_dir, install_targets, plan_targets,
+};
 use crate::package::Package;
+use crate::plan::Plan;
+use crate::registry::Registry;
+use crate::reporter::Reporter;
+use crate::shim;
+use crate::toolchain::home_of;
 
 #[derive(Debug, Parser, Default)]
 #[clap(about = "Installs a package", alias = "i")]
 pub struct Install {
     #[arg(
+        add = ArgValueCompleter::new(complete_install_args),
         help = "[branch] installs the latest version of that branch for core, lang and explorer\n[package] [branch_or_version] installs the latest branch of package or the specific version"
     )]
     pub args: Option<Vec<String>>,
@@ -22,114 +33,68 @@ pub struct Install {
         help = "The installation directory, defaults to $GREYCAT_HOME or $HOME/.greycat"
     )]
     pub dir: Option<PathBuf>,
-}
-
-impl Install {
-    pub fn run(self) -> Result<()> {
-        let dir = self.dir.unwrap_or_else(|| {
-            std::env::var("GREYCAT_HOME")
-                .map(PathBuf::from)
-                .unwrap_or_else(|_| {
-                    let mut home_dir = home::home_dir().unwrap_or_else(|| "/".into());
-                    home_dir.push(".greycat");
-                    home_dir
-                })
-        });
-
-        // clean up previous directories to prevent ghost files
-        fs::remove_dir_all(dir.join("bin")).ok();
-        fs::remove_dir_all(dir.join("lib")).ok();
-        fs::remove_dir_all(dir.join("include")).ok();
-        fs::remove_dir_all(dir.join("misc")).ok();
-
-        match self.args.as_deref() {
-            Some([branch]) => {
-                let arch = self.arch.or_else(|| Some(get_arch()));
-
-                let core = Package::new("core", arch, branch);
-                eprint!("installing {core}        ");
-                core.install_latest(&dir)?;
 
-                let lang = Package::new("lang", Some("noarch".to_string()), branch);
-                eprint!("installing {lang}        ");
-                lang.install_latest(&dir).ok();
-
-                let explorer = Package::new("explorer", Some("noarch".to_string()), branch);
-                eprint!("installing {explorer}    ");
-                explorer.install_latest(&dir).ok();
-            }
-            Some([name, branch_or_version]) => match Version::parse(branch_or_version) {
-                Ok(version) => {
-                    let arch = if name == "core" {
-                        self.arch.or_else(|| Some(get_arch()))
-                    } else {
-                        None
-                    };
-                    let pkg = Package::new(name, arch, version.pre.as_str());
+    #[arg(
+        long,
+        help = "Prints what would be installed and removed, without changing anything"
+    )]
+    pub dry_run: bool,
 
-                    eprint!("installing {pkg}...");
-                    pkg.install(version.clone(), &dir)?;
-                }
-                Err(_) => {
-                    let arch = if name == "core" {
-                        self.arch.or_else(|| Some(get_arch()))
-                    } else {
-                        None
-                    };
-                    let pkg = Package::new(name, arch, branch_or_version);
+    #[arg(long, requires = "dry_run", help = "Outputs the dry run plan as JSON")]
+    pub json: bool,
+}
 
-                    eprint!("installing {pkg}...");
-                    pkg.install_latest(&dir)?;
+impl Install {
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        let dir = install_dir(self.dir);
+
+        let targets = match self.args.as_deref() {
+            Some([branch]) => default_targets(self.arch, branch),
+            Some([name, branch_or_version]) => {
+                let arch = if name == "core" {
+                    self.arch.or_else(|| Some(get_arch()))
+                } else {
+                    None
+                };
+                match Version::parse(branch_or_version) {
+                    Ok(version) => vec![Target::version(
+                        Package::new(name, arch, version.pre.as_str()),
+                        version,
+                    )],
+                    Err(_) => vec![Target::latest(Package::new(name, arch, branch_or_version))],
                 }
-            },
+            }
             Some(_) => anyhow::bail!(
                 "too many arguments, expected either: <branch> or <name> <branch_or_version>"
             ),
-            None => {
-                let core = Package::new("core", self.arch.or_else(|| Some(get_arch())), "stable");
-                let lang = Package::new("lang", Some("noarch".to_string()), "stable");
-                let explorer = Package::new("explorer", Some("noarch".to_string()), "stable");
+            None => default_targets(self.arch, "stable"),
+        };
 
-                eprint!("installing {core}        ");
-                core.install_latest(&dir)?;
-                eprint!("installing {lang}        ");
-                lang.install_latest(&dir).ok();
-                eprint!("installing {explorer}    ");
-                explorer.install_latest(&dir).ok();
-            }
+        let registry = Registry::default();
+        if self.dry_run {
+            let plan = plan_targets(&dir, targets, &registry, reporter)?;
+            return print_plan(&plan, self.json);
         }
 
-        #[cfg(not(target_os = "windows"))]
-        for entry in fs::read_dir(dir.join("bin"))? {
-            let entry = entry?;
-            let filepath = entry.path();
-            if filepath.is_file() {
-                let mut perm = fs::metadata(&filepath)?.permissions();
-                std::os::unix::fs::PermissionsExt::set_mode(&mut perm, 0o755);
-                fs::set_permissions(&filepath, perm)?;
-            }
-        }
+        install_targets(&dir, targets, &registry, reporter)?;
+        sync_shims(&dir, reporter);
 
         Ok(())
     }
 }
 
-#[cfg(all(target_arch = "x86_64", target_os = "windows"))]
-fn get_arch() -> String {
-    "x64-windows".to_owned()
-}
-
-#[cfg(all(target_arch = "x86_64", target_os = "macos"))]
-fn get_arch() -> String {
-    "x64-apple".to_owned()
-}
-
-#[cfg(all(target_arch = "aarch64", target_os = "macos"))]
-fn get_arch() -> String {
-    "arm64-apple".to_owned()
+/// Makes the shims of the home of `dir` match the binaries now installed.
+pub fn sync_shims(dir: &Path, reporter: &Reporter) {
+    if let Err(err) = shim::sync(home_of(dir)) {
+        reporter.warn(format_args!("unable to update the shims: {err:#}"));
+    }
 }
 
-#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
-fn get_arch() -> String {
-    "x64-linux".to_owned()
+pub fn print_plan(plan: &Plan, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(plan)?);
+    } else {
+        print!("{plan}");
+    }
+    Ok(())
 }
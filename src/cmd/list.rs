@@ -1,7 +1,10 @@
+use crate::cmd::{complete_branches, complete_packages};
 use crate::registry::*;
+use crate::reporter::Reporter;
 
 use anyhow::Result;
-use clap::{Parser, ArgAction};
+use clap::Parser;
+use clap_complete::engine::ArgValueCompleter;
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -9,10 +12,10 @@ use clap::{Parser, ArgAction};
     alias = "l"
 )]
 pub struct List {
-    #[arg(help = "The package name")]
+    #[arg(add = ArgValueCompleter::new(complete_packages), help = "The package name")]
     package: Option<String>,
 
-    #[arg(help = "The package branch")]
+    #[arg(add = ArgValueCompleter::new(complete_branches), help = "The package branch")]
     branch: Option<String>,
 
     #[arg(
@@ -21,38 +24,31 @@ pub struct List {
         default_value = "5"
     )]
     limit: usize,
-
-    #[arg(short, help = "Logs verbosity", action = ArgAction::Count)]
-    verbose: u8,
 }
 
 impl List {
-    pub fn run(self) -> Result<()> {
-        if self.verbose >= 2 {
-            println!("{self:?}");
-        }
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        reporter.verbose(2, format_args!("{self:?}"));
         match (self.package, self.branch) {
             (None, None) => {
                 let registry = Registry::default();
                 for package in registry.list_packages()? {
-                    if self.verbose >= 1 {
-                        println!("{package:?}");
-                    }
+                    reporter.verbose(1, format_args!("{package:?}"));
                     let name = package.path.strip_suffix('/').unwrap_or(&package.path);
                     println!("{name}");
                 }
                 Ok(())
             }
             (None, Some(branch)) => {
-                eprintln!("TODO list all packages of a specific branch {branch}");
+                reporter.info(format_args!(
+                    "TODO list all packages of a specific branch {branch}"
+                ));
                 Ok(())
             }
             (Some(package), None) => {
                 let registry = Registry::default();
                 for branch in registry.list_package_branches(&package)? {
-                    if self.verbose >= 1 {
-                        println!("{branch:?}");
-                    }
+                    reporter.verbose(1, format_args!("{branch:?}"));
                     let (_, branch) = branch.path[..branch.path.len() - 1]
                         .rsplit_once('/')
                         .unwrap();
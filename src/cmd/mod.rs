@@ -1,7 +1,25 @@
+mod completions;
+mod doctor;
+mod env;
 mod install;
 mod list;
+mod outdated;
+mod run;
+mod search;
+mod self_manage;
+mod show;
+mod uninstall;
 mod update;
 
+pub use completions::*;
+pub use doctor::*;
+pub use env::*;
 pub use install::*;
 pub use list::*;
+pub use outdated::*;
+pub use run::*;
+pub use search::*;
+pub use self_manage::*;
+pub use show::*;
+pub use uninstall::*;
 pub use update::*;
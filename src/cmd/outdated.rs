@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use clap::Parser;
+use serde::Serialize;
+
+use crate::install::{allowed_latest, install_dir};
+use crate::package::Package;
+use crate::receipt::Receipt;
+use crate::registry::Registry;
+use crate::reporter::Reporter;
+
+#[derive(Debug, Parser)]
+#[clap(
+    about = "Lists the installed packages that have a newer version available\nExits with a non-zero status when at least one package is outdated"
+)]
+pub struct Outdated {
+    #[arg(
+        long,
+        help = "The installation directory, defaults to $GREYCAT_HOME or $HOME/.greycat"
+    )]
+    dir: Option<PathBuf>,
+
+    #[arg(long, help = "Outputs the packages as JSON")]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    name: String,
+    branch: String,
+    current: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pin: Option<String>,
+    /// The latest version on the branch the pin allows, the one `gcm update` moves to
+    allowed: Option<String>,
+    latest_on_branch: Option<String>,
+    latest: Option<String>,
+    outdated: bool,
+}
+
+impl Outdated {
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        let dir = install_dir(self.dir);
+        let Some(receipt) = Receipt::load(&dir)? else {
+            bail!(
+                "no installation recorded in {}, use 'gcm install' first",
+                dir.display()
+            );
+        };
+
+        let registry = Registry::default();
+        let mut statuses = Vec::with_capacity(receipt.packages.len());
+        for installed in &receipt.packages {
+            reporter.verbose(1, format_args!("checking {}", installed.package()));
+            let current = semver::Version::parse(&installed.version)?;
+
+            let latest_on_branch = installed
+                .package()
+                .latest()
+                .ok()
+                .and_then(|latest| semver::Version::parse(&latest.version).ok());
+
+            let allowed = match &installed.pin {
+                Some(_) => allowed_latest(
+                    installed,
+                    registry.list_package_versions(&installed.name, &installed.branch, None)?,
+                ),
+                None => latest_on_branch.clone(),
+            };
+
+            let mut latest: Option<semver::Version> = None;
+            for branch in registry.list_package_branches(&installed.name)? {
+                let Some((_, branch)) = branch
+                    .path
+                    .strip_suffix('/')
+                    .and_then(|path| path.rsplit_once('/'))
+                else {
+                    continue;
+                };
+                let version = Package::new(&installed.name, None, branch)
+                    .latest()
+                    .ok()
+                    .and_then(|latest| semver::Version::parse(&latest.version).ok());
+                if version > latest {
+                    latest = version;
+                }
+            }
+
+            statuses.push(Status {
+                name: installed.name.clone(),
+                branch: installed.branch.clone(),
+                outdated: allowed.as_ref().is_some_and(|allowed| *allowed > current),
+                current: current.to_string(),
+                pin: installed.pin.as_ref().map(|pin| pin.to_string()),
+                allowed: allowed.map(|v| v.to_string()),
+                latest_on_branch: latest_on_branch.map(|v| v.to_string()),
+                latest: latest.map(|v| v.to_string()),
+            });
+        }
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&statuses)?);
+        } else {
+            println!(
+                "{:16} {:10} {:20} {:16} {:20} {:20} {:20}",
+                "package", "branch", "current", "pin", "allowed", "latest on branch", "latest"
+            );
+            for status in &statuses {
+                println!(
+                    "{:16} {:10} {:20} {:16} {:20} {:20} {:20}",
+                    status.name,
+                    status.branch,
+                    status.current,
+                    status.pin.as_deref().unwrap_or("-"),
+                    status.allowed.as_deref().unwrap_or("-"),
+                    status.latest_on_branch.as_deref().unwrap_or("-"),
+                    status.latest.as_deref().unwrap_or("-"),
+                );
+            }
+        }
+
+        let outdated = statuses.iter().filter(|status| status.outdated).count();
+        if outdated > 0 {
+            bail!("{outdated} package(s) outdated, use 'gcm update' to update them");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::Parser;
+
+use crate::cmd::sync_shims;
+use crate::install::install_dir;
+use crate::notice;
+use crate::registry::Registry;
+use crate::reporter::Reporter;
+use crate::toolchain::{self, Toolchain, exec};
+
+#[derive(Debug, Parser)]
+#[clap(
+    about = "Runs a command with a specific GreyCat version, installing it if missing\neg. gcm run +dev -- greycat serve, gcm run 6.10 -- greycat test"
+)]
+pub struct Run {
+    #[arg(
+        help = "+<branch> or +<version>[-branch], the version prefix selects its latest patch, defaults to the active toolchain"
+    )]
+    toolchain: Option<Toolchain>,
+
+    #[arg(
+        last = true,
+        required = true,
+        help = "The command to run and its arguments"
+    )]
+    command: Vec<String>,
+
+    #[arg(
+        long,
+        help = "The installation directory, defaults to $GREYCAT_HOME or $HOME/.greycat"
+    )]
+    dir: Option<PathBuf>,
+}
+
+impl Run {
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        let home = install_dir(self.dir);
+        let toolchain = match self.toolchain {
+            Some(toolchain) => Some(toolchain),
+            None => toolchain::active(&home)?.map(|(toolchain, _)| toolchain),
+        };
+        let dir = toolchain_dir(&home, toolchain, reporter)?;
+        reporter.verbose(1, format_args!("running in {}", dir.display()));
+
+        let (program, args) = self.command.split_first().unwrap();
+        notice::check_before_exec(reporter);
+        exec(&dir, program, args)
+    }
+}
+
+/// The installation of `toolchain` in `home`, installed when missing, or `home` itself without a toolchain.
+pub fn toolchain_dir(
+    home: &Path,
+    toolchain: Option<Toolchain>,
+    reporter: &Reporter,
+) -> Result<PathBuf> {
+    let Some(toolchain) = toolchain else {
+        return Ok(home.to_path_buf());
+    };
+    if let Some(dir) = toolchain.find(home)? {
+        return Ok(dir);
+    }
+    let dir = toolchain.resolve(home, &Registry::default(), reporter)?;
+    sync_shims(&dir, reporter);
+    Ok(dir)
+}
@@ -0,0 +1,182 @@
+use anyhow::Result;
+use clap::Parser;
+use serde::Serialize;
+
+use crate::package::Package;
+use crate::registry::Registry;
+use crate::reporter::Reporter;
+
+#[derive(Debug, Parser)]
+#[clap(
+    about = "Searches packages by name, description and tags\neg. gcm search web",
+    alias = "s"
+)]
+pub struct Search {
+    #[arg(help = "The search term")]
+    query: String,
+
+    #[arg(
+        long,
+        help = "Limit the number of packages displayed",
+        default_value = "10"
+    )]
+    limit: usize,
+
+    #[arg(long, help = "Outputs the matches as JSON")]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Match {
+    name: String,
+    score: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    branches: Vec<Branch>,
+}
+
+#[derive(Debug, Serialize)]
+struct Branch {
+    name: String,
+    latest: Option<String>,
+}
+
+impl Search {
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        let query = self.query.to_lowercase();
+        let registry = Registry::default();
+
+        let mut matches = Vec::new();
+        for package in registry.list_packages()? {
+            let name = package
+                .path
+                .strip_suffix('/')
+                .unwrap_or(&package.path)
+                .to_owned();
+            let score = score(&query, &name, package.description.as_deref(), &package.tags);
+            reporter.verbose(1, format_args!("{name}: {score}"));
+            if score > 0 {
+                matches.push(Match {
+                    name,
+                    score,
+                    description: package.description,
+                    tags: package.tags,
+                    branches: Vec::new(),
+                });
+            }
+        }
+
+        matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+        matches.truncate(self.limit);
+
+        for m in &mut matches {
+            for branch in registry.list_package_branches(&m.name)? {
+                let Some((_, branch)) = branch
+                    .path
+                    .strip_suffix('/')
+                    .and_then(|path| path.rsplit_once('/'))
+                else {
+                    continue;
+                };
+                let latest = Package::new(&m.name, None, branch)
+                    .latest()
+                    .ok()
+                    .map(|version| version.version);
+                m.branches.push(Branch {
+                    name: branch.to_owned(),
+                    latest,
+                });
+            }
+        }
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&matches)?);
+            return Ok(());
+        }
+
+        if matches.is_empty() {
+            reporter.info(format_args!("no package matches \"{}\"", self.query));
+        }
+        for m in &matches {
+            match &m.description {
+                Some(description) => println!("{} - {description}", m.name),
+                None => println!("{}", m.name),
+            }
+            if !m.tags.is_empty() {
+                println!("  tags: {}", m.tags.join(", "));
+            }
+            for branch in &m.branches {
+                println!(
+                    "  {:12} {}",
+                    branch.name,
+                    branch.latest.as_deref().unwrap_or("-")
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Ranks how well `query` (lowercased) matches a package, 0 meaning no match at all.
+fn score(query: &str, name: &str, description: Option<&str>, tags: &[String]) -> u32 {
+    let name = name.to_lowercase();
+    let basename = name
+        .rsplit_once('/')
+        .map_or(name.as_str(), |(_, base)| base);
+
+    let name_score = if name == query {
+        100
+    } else if basename == query {
+        90
+    } else if name.starts_with(query) || basename.starts_with(query) {
+        80
+    } else if name.contains(query) {
+        60
+    } else {
+        fuzzy(query, &name)
+    };
+
+    let tag_score = tags
+        .iter()
+        .map(|tag| tag.to_lowercase())
+        .map(|tag| {
+            if tag == query {
+                50
+            } else if tag.contains(query) {
+                25
+            } else {
+                0
+            }
+        })
+        .max()
+        .unwrap_or(0);
+
+    let description_score = match description {
+        Some(description) if description.to_lowercase().contains(query) => 30,
+        _ => 0,
+    };
+
+    name_score.max(tag_score).max(description_score)
+}
+
+/// Subsequence match, eg. "swb" in "sdk/web", penalized by the gaps between matched chars.
+fn fuzzy(query: &str, name: &str) -> u32 {
+    let mut chars = name.chars().enumerate();
+    let mut gaps = 0;
+    let mut prev = None;
+    for q in query.chars() {
+        match chars.find(|(_, c)| *c == q) {
+            Some((i, _)) => {
+                if let Some(prev) = prev {
+                    gaps += i - prev - 1;
+                }
+                prev = Some(i);
+            }
+            None => return 0,
+        }
+    }
+    40u32.saturating_sub(gaps as u32 * 2).max(1)
+}
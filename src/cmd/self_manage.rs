@@ -0,0 +1,222 @@
+use std::fs;
+use std::io::{self, ErrorKind, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::{Parser, Subcommand};
+use sha2::{Digest, Sha256};
+use termcolor::Color;
+
+use crate::archive::Format;
+use crate::cmd::remove_init_shell;
+use crate::config::Config;
+use crate::install::{get_arch, install_dir};
+use crate::package::{self, Package, create_file};
+use crate::registry::Registry;
+use crate::reporter::Reporter;
+use crate::shim;
+
+#[derive(Debug, Parser)]
+#[clap(about = "Manages the gcm installation itself")]
+pub struct SelfManage {
+    #[command(subcommand)]
+    command: SelfCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum SelfCommand {
+    Update(SelfUpdate),
+    Uninstall(SelfUninstall),
+}
+
+#[derive(Debug, Parser)]
+#[clap(
+    about = "Replaces gcm with the latest release for the current target\nThe archive is verified against the '.sha256' published next to it"
+)]
+struct SelfUpdate {
+    #[arg(
+        long,
+        help = "The release branch to update from",
+        default_value = "stable"
+    )]
+    branch: String,
+}
+
+#[derive(Debug, Parser)]
+#[clap(about = "Removes gcm")]
+struct SelfUninstall {
+    #[arg(long, help = "Also removes the GreyCat installation directory")]
+    all: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Removes the installation directory without asking, required with --all outside of a terminal"
+    )]
+    yes: bool,
+
+    #[arg(
+        long,
+        help = "The GreyCat installation directory, defaults to $GREYCAT_HOME or $HOME/.greycat"
+    )]
+    dir: Option<PathBuf>,
+}
+
+impl SelfManage {
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        match self.command {
+            SelfCommand::Update(cmd) => cmd.run(reporter),
+            SelfCommand::Uninstall(cmd) => cmd.run(reporter),
+        }
+    }
+}
+
+impl SelfUpdate {
+    fn run(self, reporter: &Reporter) -> Result<()> {
+        let exe = std::env::current_exe().context("unable to locate the gcm executable")?;
+        // left behind by a previous update on platforms that cannot replace a running executable
+        fs::remove_file(exe.with_extension("old")).ok();
+
+        let current = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
+        let package = Package::new("gcm", Some(get_arch()), &self.branch);
+        let latest = package
+            .latest()
+            .with_context(|| format!("unable to find the latest {package}"))?;
+        let version = semver::Version::parse(&latest.version)?;
+        if version <= current {
+            reporter.info(format_args!("gcm {current} is up to date"));
+            return Ok(());
+        }
+
+        reporter.status(format_args!("updating gcm {current} -> {version} "));
+        let registry = Registry::default();
+        let limit = Config::load(&install_dir(None))?
+            .extract_limits
+            .max_total_bytes;
+        let archive = package::open(&registry, &package, &latest, Format::Zip, limit, reporter);
+        let archive = archive.and_then(|(mut reader, _)| {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        });
+        reporter.finish_progress();
+        let archive = archive?;
+
+        let filepath = package.filepath_as(&latest, Format::Zip);
+        let url = format!("{}.sha256", registry.file_url(&filepath));
+        let expected = ureq::get(&url)
+            .call()
+            .with_context(|| format!("unable to fetch the checksum {url}"))?
+            .into_string()?;
+        let expected = expected.split_whitespace().next().unwrap_or_default();
+        let actual = format!("{:x}", Sha256::digest(&archive));
+        if !expected.eq_ignore_ascii_case(&actual) {
+            bail!("checksum mismatch for {url}: expected {expected}, got {actual}");
+        }
+
+        let name = format!("gcm{}", std::env::consts::EXE_SUFFIX);
+        let mut zip = zip::ZipArchive::new(io::Cursor::new(archive))?;
+        let index = (0..zip.len())
+            .find(|&i| {
+                zip.by_index_raw(i).is_ok_and(|file| {
+                    file.enclosed_name()
+                        .and_then(Path::file_name)
+                        .is_some_and(|file_name| *file_name == *name)
+                })
+            })
+            .with_context(|| format!("no '{name}' in the release archive"))?;
+
+        let new = exe.with_extension("new");
+        {
+            let mut file = zip.by_index(index)?;
+            let mut out = fs::File::create(&new)
+                .with_context(|| format!("unable to write {}", new.display()))?;
+            io::copy(&mut file, &mut out)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                out.set_permissions(fs::Permissions::from_mode(0o755))?;
+            }
+        }
+        replace_executable(&new, &exe)
+            .with_context(|| format!("unable to replace {}", exe.display()))?;
+        // the shims are copies of, or links to, the previous executable
+        if let Err(err) = shim::sync(&install_dir(None)) {
+            reporter.warn(format_args!("unable to update the shims: {err:#}"));
+        }
+
+        reporter.done(Color::Green, "done");
+        Ok(())
+    }
+}
+
+/// Atomically moves `new` over the running executable `exe`.
+fn replace_executable(new: &Path, exe: &Path) -> io::Result<()> {
+    match fs::rename(new, exe) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::ExecutableFileBusy => {
+            // same trick as when extracting over a running greycat
+            let mut out = create_file(exe)?;
+            io::copy(&mut fs::File::open(new)?, &mut out)?;
+            fs::remove_file(new)
+        }
+        Err(_) => {
+            // eg. on Windows, a running executable cannot be replaced but can be moved away
+            let old = exe.with_extension("old");
+            fs::rename(exe, &old)?;
+            fs::rename(new, exe).inspect_err(|_| {
+                fs::rename(&old, exe).ok();
+            })
+        }
+    }
+}
+
+impl SelfUninstall {
+    fn run(self, reporter: &Reporter) -> Result<()> {
+        let dir = install_dir(self.dir);
+        if self.all && dir.exists() && !self.yes && !confirm(&dir)? {
+            reporter.info("nothing removed");
+            return Ok(());
+        }
+        // the shims are copies of gcm, of no use once it is gone
+        let remove = if self.all { dir } else { shim::shims_dir(&dir) };
+        if remove.exists() {
+            reporter.info(format_args!("removing {}", remove.display()));
+            fs::remove_dir_all(&remove)
+                .with_context(|| format!("unable to remove {}", remove.display()))?;
+        }
+        for rc_file in remove_init_shell()? {
+            reporter.info(format_args!(
+                "removing the gcm lines of {}",
+                rc_file.display()
+            ));
+        }
+
+        let exe = std::env::current_exe().context("unable to locate the gcm executable")?;
+        // already gone when it lived in the installation directory
+        if exe.exists() {
+            reporter.info(format_args!("removing {}", exe.display()));
+            fs::remove_file(&exe).with_context(|| format!("unable to remove {}", exe.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Asks whether `dir` may be removed, failing when nobody is there to answer.
+fn confirm(dir: &Path) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        bail!(
+            "--all removes {} and everything installed in it, add --yes to confirm",
+            dir.display()
+        );
+    }
+    eprint!(
+        "remove {} and everything installed in it? [y/N] ",
+        dir.display()
+    );
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
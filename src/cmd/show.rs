@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use clap::Parser;
+use clap_complete::engine::ArgValueCompleter;
+use serde::Serialize;
+use termcolor::Color;
+
+use crate::cmd::complete_package_spec;
+use crate::config::Config;
+use crate::install::{get_arch, install_dir};
+use crate::package::{self, ArchiveEntry, Package, Version};
+use crate::progress::FmtBytes;
+use crate::registry::Registry;
+use crate::reporter::Reporter;
+
+#[derive(Debug, Parser)]
+#[clap(
+    about = "Shows detailed information about a package without installing it\neg. gcm show core@stable, gcm show explorer@7.0.1-dev"
+)]
+pub struct Show {
+    #[arg(
+        add = ArgValueCompleter::new(complete_package_spec),
+        help = "<package>[@branch|version], defaults to the 'stable' branch"
+    )]
+    package: String,
+
+    #[arg(long, help = "The architecture of the archive to inspect")]
+    arch: Option<String>,
+
+    #[arg(long, help = "Outputs the information as JSON")]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct PackageInfo {
+    name: String,
+    branch: String,
+    version: String,
+    arches: Vec<String>,
+    arch: Option<String>,
+    url: String,
+    size: u64,
+    published: Option<DateTime<Local>>,
+    sha256: String,
+    files: Vec<ArchiveEntry>,
+}
+
+impl Show {
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        let (name, branch_or_version) = self
+            .package
+            .split_once('@')
+            .unwrap_or((&self.package, "stable"));
+
+        let (branch, version) = match semver::Version::parse(branch_or_version) {
+            Ok(version) => (version.pre.to_string(), version),
+            Err(_) => {
+                let latest = Package::new(name, None, branch_or_version)
+                    .latest()
+                    .with_context(|| {
+                        format!("no version found for \"{name}@{branch_or_version}\"")
+                    })?;
+                let version = semver::Version::parse(&latest.version)?;
+                (branch_or_version.to_owned(), version)
+            }
+        };
+        let version = Version::from(&version);
+
+        let registry = Registry::default();
+        let limit = Config::load(&install_dir(None))?
+            .extract_limits
+            .max_total_bytes;
+        let listing = registry.list_files(&format!("{name}/{branch}/{}/", version.major_minor))?;
+        let arches: Vec<String> = listing
+            .iter()
+            .filter_map(|file| file.path.strip_suffix('/'))
+            .filter_map(|path| path.rsplit_once('/'))
+            .map(|(_, arch)| arch.to_owned())
+            .collect();
+
+        let arch = if arches.is_empty() {
+            None
+        } else {
+            let preferred = self.arch.unwrap_or_else(get_arch);
+            [preferred.as_str(), "noarch"]
+                .into_iter()
+                .find(|arch| arches.iter().any(|a| a == arch))
+                .or(arches.first().map(String::as_str))
+                .map(str::to_owned)
+        };
+
+        let package = Package::new(name, arch.clone(), &branch);
+        let filepath =
+            package.filepath_as(&version, package::format(&registry, &package, &version));
+        let published = match &arch {
+            Some(arch) => {
+                registry.list_files(&format!("{name}/{branch}/{}/{arch}/", version.major_minor))?
+            }
+            None => listing,
+        }
+        .into_iter()
+        .find(|file| file.path == filepath)
+        .map(|file| file.last_modification);
+
+        reporter.status(format_args!("inspecting {filepath} "));
+        let archive = package::inspect(&registry, &package, &version, limit, reporter);
+        reporter.finish_progress();
+        let archive = archive?;
+        reporter.done(Color::Green, "done");
+
+        let info = PackageInfo {
+            name: name.to_owned(),
+            branch,
+            url: registry.file_url(&package.filepath_as(&version, archive.format)),
+            version: version.version,
+            arches,
+            arch,
+            size: archive.size,
+            published,
+            sha256: archive.sha256,
+            files: archive.entries,
+        };
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&info)?);
+            return Ok(());
+        }
+
+        println!("{} {}", info.name, info.version);
+        println!("branch:     {}", info.branch);
+        println!("arches:     {}", info.arches.join(", "));
+        if let Some(arch) = &info.arch {
+            println!("arch:       {arch}");
+        }
+        println!("url:        {}", info.url);
+        println!("size:       {}", FmtBytes(info.size));
+        if let Some(published) = &info.published {
+            println!("published:  {}", published.format("%Y-%m-%d %H:%M:%S"));
+        }
+        println!("sha256:     {}", info.sha256);
+        println!("files ({}):", info.files.len());
+        for file in &info.files {
+            let mode = match file.mode {
+                Some(mode) => format!("{:04o}", mode & 0o7777),
+                None => "----".to_string(),
+            };
+            println!(
+                "  {mode} {:>10}  {}",
+                FmtBytes(file.size).to_string(),
+                file.path
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCompleter;
+
+use crate::cmd::{complete_installed, print_plan, sync_shims};
+use crate::install::{install_dir, plan_uninstall, uninstall};
+use crate::reporter::Reporter;
+
+#[derive(Debug, Parser)]
+#[clap(
+    about = "Uninstalls packages\neg. gcm uninstall, gcm uninstall sdk/web",
+    alias = "rm"
+)]
+pub struct Uninstall {
+    #[arg(
+        add = ArgValueCompleter::new(complete_installed),
+        help = "The packages to uninstall, defaults to every installed package"
+    )]
+    packages: Vec<String>,
+
+    #[arg(
+        long,
+        help = "The installation directory, defaults to $GREYCAT_HOME or $HOME/.greycat"
+    )]
+    dir: Option<PathBuf>,
+
+    #[arg(long, help = "Prints what would be removed, without changing anything")]
+    pub dry_run: bool,
+
+    #[arg(long, requires = "dry_run", help = "Outputs the dry run plan as JSON")]
+    json: bool,
+}
+
+impl Uninstall {
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        let dir = install_dir(self.dir);
+        let (plan, receipt) = plan_uninstall(&dir, &self.packages)?;
+        if self.dry_run {
+            return print_plan(&plan, self.json);
+        }
+        uninstall(&plan, receipt, reporter)?;
+        sync_shims(&dir, reporter);
+        Ok(())
+    }
+}
@@ -1,42 +1,139 @@
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use anyhow::{Context, Result};
 use clap::Parser;
 
-use crate::Install;
+use crate::cmd::Install;
+use crate::cmd::{print_plan, sync_shims};
+use crate::install::{Target, install_dir, install_targets, plan_targets, resolve_updates};
+use crate::receipt::Receipt;
+use crate::registry::Registry;
+use crate::reporter::Reporter;
 
 #[derive(Debug, Parser)]
 #[clap(
-    about = "Updates the currently installed packages on the same branch\nIf no installation found, installs latest 'stable'",
+    about = "Updates every installed package to the latest version of its branch\nIf no installation found, installs latest 'stable'",
     alias = "u"
 )]
-pub struct Update {}
+pub struct Update {
+    #[arg(
+        long,
+        help = "The installation directory, defaults to $GREYCAT_HOME or $HOME/.greycat"
+    )]
+    pub dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Prints what would be updated and removed, without changing anything"
+    )]
+    pub dry_run: bool,
+
+    #[arg(long, requires = "dry_run", help = "Outputs the dry run plan as JSON")]
+    pub json: bool,
+}
 
 impl Update {
-    pub fn run(self) -> Result<()> {
-        let output = Command::new("greycat")
-            .arg("-vv")
-            .output()
-            .context("unable to run 'greycat -vv'")?;
-        let buf =
-            String::from_utf8(output.stdout).context("'greycat -vv' returned non-UTF8 data")?;
-
-        if let Some((version, arch)) = buf.split_once(' ') {
-            let arch = arch
-                .strip_prefix('(')
-                .unwrap_or(arch)
-                .strip_suffix(")\n")
-                .unwrap_or(arch);
-            let version = semver::Version::parse(version)?;
-
-            Install {
-                arch: Some(arch.to_string()),
-                args: Some(vec![version.pre.to_string()]),
+    pub fn run(self, reporter: &Reporter) -> Result<()> {
+        let dir = install_dir(self.dir);
+
+        if let Some(receipt) = Receipt::load(&dir)?
+            && !receipt.packages.is_empty()
+        {
+            let installed = receipt
+                .packages
+                .iter()
+                .map(|p| format!("{}@{} {}", p.name, p.branch, p.version))
+                .collect::<Vec<_>>()
+                .join(", ");
+            reporter.info(format_args!("updating {} ({installed})", dir.display()));
+            let registry = Registry::default();
+            let targets = resolve_updates(&receipt, &registry, reporter)?;
+            if self.dry_run {
+                let plan = plan_targets(&dir, targets, &registry, reporter)?;
+                return print_plan(&plan, self.json);
+            }
+            return update_receipt(&dir, receipt, targets, reporter);
+        }
+
+        let greycat = dir
+            .join("bin")
+            .join(format!("greycat{}", std::env::consts::EXE_SUFFIX));
+        if !greycat.is_file() {
+            reporter.warn(format_args!(
+                "no installation found in {}, installing latest 'stable'",
+                dir.display()
+            ));
+            return Install {
+                dir: Some(dir),
+                dry_run: self.dry_run,
+                json: self.json,
                 ..Default::default()
             }
-            .run()
+            .run(reporter);
+        }
+
+        let output = Command::new(&greycat)
+            .arg("-vv")
+            .output()
+            .with_context(|| format!("unable to run '{} -vv'", greycat.display()))?;
+        let buf = String::from_utf8(output.stdout)
+            .with_context(|| format!("'{} -vv' returned non-UTF8 data", greycat.display()))?;
+
+        let (version, arch) = buf.trim().split_once(' ').with_context(|| {
+            format!(
+                "unable to detect the installed version from '{} -vv': {buf:?}",
+                greycat.display()
+            )
+        })?;
+        let arch = arch
+            .strip_prefix('(')
+            .and_then(|arch| arch.strip_suffix(')'))
+            .unwrap_or(arch);
+        let version = semver::Version::parse(version)?;
+
+        reporter.info(format_args!(
+            "updating {} ({version} {arch}, detected from {})",
+            dir.display(),
+            greycat.display()
+        ));
+
+        Install {
+            arch: Some(arch.to_string()),
+            args: Some(vec![version.pre.to_string()]),
+            dir: Some(dir),
+            dry_run: self.dry_run,
+            json: self.json,
+        }
+        .run(reporter)
+    }
+}
+
+fn update_receipt(
+    dir: &Path,
+    receipt: Receipt,
+    targets: Vec<Target>,
+    reporter: &Reporter,
+) -> Result<()> {
+    if targets.is_empty() {
+        reporter.info("everything is up to date");
+        return Ok(());
+    }
+
+    let updated = install_targets(dir, targets, &Registry::default(), reporter)?;
+    sync_shims(dir, reporter);
+
+    for before in &receipt.packages {
+        let after = updated
+            .get(&before.name)
+            .map_or(before.version.as_str(), |p| p.version.as_str());
+        let package = before.package();
+        if after == before.version {
+            println!("{:20} {} (unchanged)", package.to_string(), before.version);
         } else {
-            Install::default().run()
+            println!("{:20} {} -> {after}", package.to_string(), before.version);
         }
     }
+
+    Ok(())
 }
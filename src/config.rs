@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+const CONFIG_FILE: &str = "config.json";
+
+/// gcm settings, read from the `config.json` of an installation directory. Every field is optional.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub update_check: UpdateCheckConfig,
+    /// The toolchain run by the shims outside of projects with a `.greycat-toolchain` file, eg. `dev` or `7.0`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_toolchain: Option<String>,
+    pub extract_limits: ExtractLimits,
+    /// Applies the setuid, setgid and sticky bits of the archive entries, stripped by default
+    pub keep_special_mode_bits: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdateCheckConfig {
+    /// Can also be disabled with `GCM_NO_UPDATE_CHECK=1`
+    pub enabled: bool,
+    /// Minimum delay between two checks against the registry
+    pub interval_hours: u64,
+}
+
+impl Default for UpdateCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_hours: 24,
+        }
+    }
+}
+
+/// Bounds what extracting a package may write, against corrupted or malicious archives
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractLimits {
+    /// Uncompressed bytes of the whole archive
+    pub max_total_bytes: u64,
+    /// Uncompressed bytes of a single entry
+    pub max_file_bytes: u64,
+    /// Uncompressed over compressed size of a single entry, only checked for entries over 1 MiB
+    pub max_ratio: u64,
+    pub max_entries: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_total_bytes: 16 * 1024 * 1024 * 1024,
+            max_file_bytes: 4 * 1024 * 1024 * 1024,
+            max_ratio: 200,
+            max_entries: 100_000,
+        }
+    }
+}
+
+impl Config {
+    /// The config of the installation in `home`, shared with its toolchains
+    pub fn path(home: &Path) -> PathBuf {
+        home.join(CONFIG_FILE)
+    }
+
+    /// Returns the default configuration when there is no config file.
+    pub fn load(home: &Path) -> Result<Self> {
+        let path = Self::path(home);
+        match fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)
+                .with_context(|| format!("invalid config {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+}
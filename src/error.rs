@@ -0,0 +1,110 @@
+use std::fmt;
+use std::io;
+
+use crate::archive::InvalidArchive;
+
+/// The error returned by the library API, whose [`kind`](Error::kind) tells what went wrong.
+///
+/// Its message is the outermost context, eg. `extracting package content`, the underlying causes being available
+/// through [`source`](std::error::Error::source).
+pub struct Error {
+    kind: ErrorKind,
+    inner: anyhow::Error,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The registry could not be reached, or answered with an unexpected status
+    Registry,
+    /// The package, version or toolchain does not exist in the registry
+    NotFound,
+    /// A package archive is corrupted, unsafe or goes over the extraction limits
+    InvalidArchive,
+    /// Reading or writing the installation directory failed
+    Io,
+    /// Anything else, eg. an invalid receipt or configuration
+    Other,
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(inner: anyhow::Error) -> Self {
+        Self {
+            kind: kind_of(&inner),
+            inner,
+        }
+    }
+}
+
+/// Classifies an internal error from the first cause that tells its kind.
+pub(crate) fn kind_of(err: &anyhow::Error) -> ErrorKind {
+    err.chain()
+        .find_map(|cause| {
+            // already classified, by a public API used internally
+            if let Some(err) = cause.downcast_ref::<Error>() {
+                return Some(err.kind);
+            }
+            if let Some(err) = cause.downcast_ref::<ureq::Error>() {
+                return Some(match err {
+                    ureq::Error::Status(404, _) => ErrorKind::NotFound,
+                    _ => ErrorKind::Registry,
+                });
+            }
+            #[cfg(feature = "async")]
+            if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+                return Some(match err.status() {
+                    Some(reqwest::StatusCode::NOT_FOUND) => ErrorKind::NotFound,
+                    _ => ErrorKind::Registry,
+                });
+            }
+            let err = cause.downcast_ref::<io::Error>()?;
+            // why an archive is rejected is carried by the io error itself, not as its source
+            let invalid = err
+                .get_ref()
+                .is_some_and(|err| err.is::<InvalidArchive>() || err.is::<zip::result::ZipError>());
+            Some(if invalid {
+                ErrorKind::InvalidArchive
+            } else {
+                ErrorKind::Io
+            })
+        })
+        .unwrap_or(ErrorKind::Other)
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        anyhow::Error::from(err).into()
+    }
+}
+
+impl From<ureq::Error> for Error {
+    fn from(err: ureq::Error) -> Self {
+        anyhow::Error::from(err).into()
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {:?}", self.kind, self.inner)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner.source()
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{self, Cached};
+use crate::install::install_dir;
+use crate::registry::Registry;
+
+const CACHE_ENTRY: &str = "index.json";
+
+/// Package names and branches are stable enough to be cached for a day
+const TTL_HOURS: i64 = 24;
+
+/// The package names and, once asked for, the branches of each package, as published by the registry.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Index {
+    pub packages: Vec<String>,
+    #[serde(default)]
+    pub branches: BTreeMap<String, Vec<String>>,
+}
+
+/// The names of every package in the registry, eg. `core`, `sdk/web`.
+///
+/// A stale index is used as is when the registry is unreachable.
+pub fn packages() -> Vec<String> {
+    let (index, fresh) = load();
+    if fresh && !index.packages.is_empty() {
+        return index.packages;
+    }
+    match Registry::default().list_packages() {
+        Ok(files) => {
+            let packages: Vec<String> = files
+                .iter()
+                .map(|file| file.path.trim_end_matches('/').to_string())
+                .collect();
+            // stale branches are dropped along with the stale package names
+            let branches = if fresh {
+                index.branches
+            } else {
+                BTreeMap::new()
+            };
+            cache::write(
+                &install_dir(None),
+                CACHE_ENTRY,
+                Index {
+                    packages: packages.clone(),
+                    branches,
+                },
+            )
+            .ok();
+            packages
+        }
+        Err(_) => index.packages,
+    }
+}
+
+/// The branches of the package `name`, eg. `stable`, `dev`.
+pub fn branches(name: &str) -> Vec<String> {
+    let (mut index, fresh) = load();
+    if fresh && let Some(branches) = index.branches.get(name) {
+        return branches.clone();
+    }
+    match fetch_branches(name) {
+        Ok(branches) => {
+            if !fresh {
+                // writing the entry renews it, do not let stale package names pass as fresh
+                index = Index::default();
+            }
+            index.branches.insert(name.to_string(), branches.clone());
+            cache::write(&install_dir(None), CACHE_ENTRY, index).ok();
+            branches
+        }
+        Err(_) => index.branches.remove(name).unwrap_or_default(),
+    }
+}
+
+fn load() -> (Index, bool) {
+    match cache::read::<Index>(&install_dir(None), CACHE_ENTRY) {
+        Some(cached) => {
+            let fresh = cached.is_fresh(chrono::Duration::hours(TTL_HOURS));
+            let Cached { value, .. } = cached;
+            (value, fresh)
+        }
+        None => (Index::default(), false),
+    }
+}
+
+fn fetch_branches(name: &str) -> Result<Vec<String>> {
+    Ok(Registry::default()
+        .list_package_branches(name)?
+        .iter()
+        .filter_map(|file| {
+            let (_, branch) = file.path.trim_end_matches('/').rsplit_once('/')?;
+            Some(branch.to_string())
+        })
+        .collect())
+}
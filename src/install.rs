@@ -0,0 +1,536 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use semver::{Comparator, Op, Version, VersionReq};
+
+use crate::ErrorKind;
+use crate::config::Config;
+use crate::observer::{Observer, Skipped};
+use crate::package::{self, Package, Spooled, backup_path};
+use crate::plan::{Action, Plan};
+use crate::receipt::{InstalledPackage, Receipt, remove_files};
+use crate::registry::{PackageVersion, Registry};
+use crate::toolchain::home_of;
+
+/// The directories replaced as a whole when installing over an installation without receipt
+pub const LEGACY_DIRS: [&str; 4] = ["bin", "lib", "include", "misc"];
+
+/// A package to install, either in its latest version or in a specific one
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Target {
+    pub package: Package,
+    pub version: Option<Version>,
+    pub pin: Option<VersionReq>,
+    /// Failing to install an optional package is not an error
+    pub optional: bool,
+}
+
+impl Target {
+    /// The latest version of `package`
+    pub fn latest(package: Package) -> Self {
+        Self {
+            package,
+            version: None,
+            pin: None,
+            optional: false,
+        }
+    }
+
+    /// `version` of `package`, pinned so that updates leave it as is
+    pub fn version(package: Package, version: Version) -> Self {
+        let pin = VersionReq {
+            comparators: vec![Comparator {
+                op: Op::Exact,
+                major: version.major,
+                minor: Some(version.minor),
+                patch: Some(version.patch),
+                pre: version.pre.clone(),
+            }],
+        };
+        Self {
+            package,
+            version: Some(version),
+            pin: Some(pin),
+            optional: false,
+        }
+    }
+
+    /// core, lang and explorer on `branch`, for the current platform
+    pub fn defaults(branch: &str) -> Vec<Self> {
+        default_targets(None, branch)
+    }
+}
+
+/// core, lang and explorer on the given branch
+pub fn default_targets(arch: Option<String>, branch: &str) -> Vec<Target> {
+    let arch = arch.or_else(|| Some(get_arch()));
+    [
+        ("core", arch, false),
+        ("lang", Some("noarch".to_string()), true),
+        ("explorer", Some("noarch".to_string()), true),
+    ]
+    .into_iter()
+    .map(|(name, arch, optional)| Target {
+        package: Package::new(name, arch, branch),
+        version: None,
+        pin: None,
+        optional,
+    })
+    .collect()
+}
+
+/// Installs `targets` in `dir`, replacing the files of their previously installed versions.
+///
+/// Other packages recorded in the receipt of `dir` are left untouched. The files modified since they were extracted
+/// are either kept in place when the new version does not have them anymore, or backed up next to the new ones, as
+/// are the files added by the user that a package overwrites.
+pub fn install_targets(
+    dir: &Path,
+    targets: Vec<Target>,
+    registry: &Registry,
+    observer: &dyn Observer,
+) -> Result<Receipt> {
+    let mut resolved = Vec::new();
+    for target in targets {
+        if target.version.is_some() {
+            resolved.push(target);
+            continue;
+        }
+        observer.resolving(&target.package);
+        let found = latest_found(registry.latest(&target.package));
+        resolved.extend(resolve_target(target, found, observer)?);
+    }
+    // the archives are bounded by what they may extract
+    let limit = Config::load(home_of(dir))?.extract_limits.max_total_bytes;
+    install_fetched(dir, resolved, observer, |package, version| {
+        package::fetch(registry, home_of(dir), package, version, limit, observer)
+    })
+}
+
+/// [`install_targets`], the version of `targets` being resolved already and their archives downloaded by `fetch`
+pub(crate) fn install_fetched(
+    dir: &Path,
+    targets: Vec<Target>,
+    observer: &dyn Observer,
+    mut fetch: impl FnMut(&Package, &package::Version) -> Result<Option<Spooled>>,
+) -> Result<Receipt> {
+    // the toolchains share the config of the installation they are in
+    let config = Config::load(home_of(dir))?;
+    // only replaced by the first package installed, once its archive has been validated
+    let (mut receipt, mut legacy) = match Receipt::load(dir)? {
+        Some(receipt) => (receipt, &[][..]),
+        None => (Receipt::new(dir), &LEGACY_DIRS[..]),
+    };
+
+    for target in targets {
+        let package = target.package;
+        let Some(version) = target.version else {
+            bail!("no version resolved for {package}");
+        };
+        observer.installing(&package);
+
+        let version = package::Version::from(&version);
+        // a failed extraction leaves the installation directory untouched
+        let res = fetch(&package, &version).and_then(|archive| match archive {
+            Some(archive) => {
+                let keep = |file: &Path| receipt.is_user_file(dir, file);
+                package
+                    .install_from(archive, version, dir, &keep, legacy, &config, observer)
+                    .map(Some)
+            }
+            None => {
+                observer.skipped(&package, Skipped::NotFound);
+                Ok(None)
+            }
+        });
+        let installed = match res {
+            Ok(Some(installed)) => installed,
+            Ok(None) => continue,
+            Err(err) if !target.optional => return Err(err),
+            Err(err) => {
+                observer.skipped(&package, Skipped::Failed(err.as_ref()));
+                continue;
+            }
+        };
+
+        legacy = &[];
+        for (file, backup) in &installed.backups {
+            observer.kept(&dir.join(file), Some(backup));
+        }
+        // the modified files the new version does not have anymore are left in place
+        let mut stale = receipt.exclusive_files(&package.name, &installed.files);
+        let written: HashSet<&PathBuf> = installed.files.iter().collect();
+        let modified = receipt
+            .get(&package.name)
+            .map(|installed| installed.modified_files(dir))
+            .unwrap_or_default();
+        for file in modified.iter().filter(|file| !written.contains(file)) {
+            stale.retain(|stale| stale != file);
+            observer.kept(&dir.join(file), None);
+        }
+        receipt.record(&package, installed, target.pin);
+        remove_files(dir, &stale)?;
+        receipt.save(dir)?;
+    }
+
+    Ok(receipt)
+}
+
+/// `target` once the lookup of its latest version `found` it, `None` when skipped.
+///
+/// Failing to find the latest version of an optional target skips it, as does the registry not publishing it.
+pub(crate) fn resolve_target(
+    mut target: Target,
+    found: Result<Option<Version>>,
+    observer: &dyn Observer,
+) -> Result<Option<Target>> {
+    match found {
+        Ok(Some(latest)) => {
+            target.version = Some(latest);
+            Ok(Some(target))
+        }
+        Ok(None) => {
+            observer.installing(&target.package);
+            observer.skipped(&target.package, Skipped::NotFound);
+            Ok(None)
+        }
+        Err(err) if !target.optional => Err(err),
+        Err(err) => {
+            observer.installing(&target.package);
+            observer.skipped(&target.package, Skipped::Failed(err.as_ref()));
+            Ok(None)
+        }
+    }
+}
+
+/// Resolves, for every package recorded in `receipt`, the latest version of its branch allowed by its pin.
+///
+/// Only the packages that would change are returned.
+pub fn resolve_updates(
+    receipt: &Receipt,
+    registry: &Registry,
+    observer: &dyn Observer,
+) -> Result<Vec<Target>> {
+    let mut targets = Vec::new();
+    for installed in &receipt.packages {
+        let package = installed.package();
+        observer.resolving(&package);
+        let available = match &installed.pin {
+            Some(_) => Available::Versions(registry.list_package_versions(
+                &installed.name,
+                &installed.branch,
+                None,
+            )?),
+            None => Available::Latest(latest_found(registry.latest(&package))?),
+        };
+        targets.extend(update_target(installed, available, observer)?);
+    }
+
+    Ok(targets)
+}
+
+/// The latest version from what the registry answered when asked for it, `None` when it does not publish it
+pub(crate) fn latest_found(latest: crate::Result<package::Version>) -> Result<Option<Version>> {
+    match latest {
+        Ok(latest) => Ok(Some(Version::parse(&latest.version)?)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// What the registry answered about the versions an installed package may be updated to
+pub(crate) enum Available {
+    /// Every version of its branch, for a pinned package
+    Versions(Vec<PackageVersion>),
+    /// The latest version of its branch, `None` when the registry does not publish it
+    Latest(Option<Version>),
+}
+
+/// The latest of `versions` the pin of `installed` allows
+pub fn allowed_latest(
+    installed: &InstalledPackage,
+    versions: Vec<PackageVersion>,
+) -> Option<Version> {
+    versions
+        .into_iter()
+        .map(|version| version.version)
+        .filter(|version| installed.allows(version))
+        .max()
+}
+
+/// The target updating `installed` to the latest version `available` its pin allows, if newer
+pub(crate) fn update_target(
+    installed: &InstalledPackage,
+    available: Available,
+    observer: &dyn Observer,
+) -> Result<Option<Target>> {
+    let latest = match available {
+        Available::Versions(versions) => allowed_latest(installed, versions),
+        Available::Latest(Some(latest)) => Some(latest),
+        Available::Latest(None) => {
+            let package = installed.package();
+            observer.warning(&format_args!("no latest version of {package} found"));
+            None
+        }
+    };
+    let current = Version::parse(&installed.version)?;
+    if latest.as_ref().is_none_or(|latest| *latest <= current) {
+        return Ok(None);
+    }
+    Ok(Some(Target {
+        package: installed.package(),
+        version: latest,
+        pin: installed.pin.clone(),
+        optional: installed.name != "core",
+    }))
+}
+
+/// Computes what uninstalling the packages `names` from `dir` removes, every package when empty, along with what
+/// is left of the receipt afterwards.
+///
+/// Without a receipt, only a complete uninstall is possible, which removes the [`LEGACY_DIRS`].
+pub fn plan_uninstall(dir: &Path, names: &[String]) -> Result<(Plan, Option<Receipt>)> {
+    let mut plan = Plan::new(dir.to_path_buf());
+
+    let Some(mut receipt) = Receipt::load(dir)? else {
+        if !names.is_empty() {
+            bail!(
+                "no installation recorded in {}, only a complete uninstall is possible",
+                dir.display()
+            );
+        }
+        for subdir in LEGACY_DIRS {
+            if dir.join(subdir).exists() {
+                plan.actions.push(Action::Remove {
+                    path: subdir.into(),
+                });
+            }
+        }
+        return Ok((plan, None));
+    };
+
+    let names: Vec<String> = if names.is_empty() {
+        receipt.packages.iter().map(|p| p.name.clone()).collect()
+    } else {
+        names.to_vec()
+    };
+
+    for name in &names {
+        let Some(installed) = receipt.get(name) else {
+            bail!("{name} is not installed in {}", dir.display());
+        };
+        plan.actions.push(Action::Uninstall {
+            name: installed.name.clone(),
+            branch: installed.branch.clone(),
+            version: installed.version.clone(),
+        });
+        // modified files are left behind, as if added by the user
+        let modified = installed.modified_files(dir);
+        plan.actions
+            .extend(receipt.exclusive_files(name, &[]).into_iter().map(|path| {
+                if modified.contains(&path) {
+                    Action::Preserve { path, backup: None }
+                } else {
+                    Action::Remove { path }
+                }
+            }));
+    }
+    receipt.packages.retain(|p| !names.contains(&p.name));
+
+    Ok((plan, Some(receipt)))
+}
+
+/// Executes a `plan` from [`plan_uninstall`], then saves what is left of `receipt`, removing it once empty.
+pub fn uninstall(plan: &Plan, receipt: Option<Receipt>, observer: &dyn Observer) -> Result<()> {
+    let mut files = Vec::new();
+    for action in &plan.actions {
+        match action {
+            Action::Uninstall {
+                name,
+                branch,
+                version,
+            } => observer.uninstalling(&Package::new(name, None, branch), version),
+            Action::Remove { path } if plan.dir.join(path).is_dir() => {
+                fs::remove_dir_all(plan.dir.join(path))
+                    .with_context(|| format!("removing {}", plan.dir.join(path).display()))?;
+            }
+            Action::Remove { path } => files.push(path.clone()),
+            Action::Preserve { path, .. } => observer.kept(&plan.dir.join(path), None),
+            Action::Install { .. } | Action::Skip { .. } => (),
+        }
+    }
+    remove_files(&plan.dir, &files)?;
+
+    match receipt {
+        Some(receipt) if !receipt.packages.is_empty() => receipt.save(&plan.dir)?,
+        _ => match fs::remove_file(Receipt::path(&plan.dir)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                return Err(err).context("removing the receipt");
+            }
+            _ => (),
+        },
+    }
+
+    Ok(())
+}
+
+/// Computes what [`install_targets`] would do, downloading the archives in memory to list their files.
+pub fn plan_targets(
+    dir: &Path,
+    targets: Vec<Target>,
+    registry: &Registry,
+    observer: &dyn Observer,
+) -> Result<Plan> {
+    let mut plan = Plan::new(dir.to_path_buf());
+    let limit = Config::load(home_of(dir))?.extract_limits.max_total_bytes;
+    let receipt = Receipt::load(dir)?;
+    if receipt.is_none() {
+        for subdir in LEGACY_DIRS {
+            if dir.join(subdir).exists() {
+                plan.actions.push(Action::Remove {
+                    path: subdir.into(),
+                });
+            }
+        }
+    }
+
+    for target in targets {
+        let package = target.package;
+        observer.planning(&package);
+        let version = match target.version {
+            Some(version) => Ok(Some(version)),
+            None => latest_found(registry.latest(&package)),
+        };
+        let archive = version.and_then(|version| {
+            let Some(version) = version else {
+                return Ok(None);
+            };
+            let version = package::Version::from(&version);
+            match package::inspect(registry, &package, &version, limit, observer) {
+                Ok(archive) => Ok(Some((version, archive))),
+                Err(err) if package::is_not_found(&err) => Ok(None),
+                Err(err) => Err(err),
+            }
+        });
+        let (version, archive) = match archive {
+            Ok(Some(res)) => res,
+            Err(err) if !target.optional => return Err(err),
+            res => {
+                let reason = match res {
+                    Err(err) => {
+                        observer.skipped(&package, Skipped::Failed(err.as_ref()));
+                        format!("{err:#}")
+                    }
+                    _ => {
+                        observer.skipped(&package, Skipped::NotFound);
+                        "not found".to_string()
+                    }
+                };
+                plan.actions.push(Action::Skip {
+                    name: package.name,
+                    branch: package.branch,
+                    reason,
+                });
+                continue;
+            }
+        };
+        observer.planned(&package, &version.version);
+
+        let files: Vec<PathBuf> = archive
+            .entries
+            .iter()
+            .filter(|entry| !entry.path.ends_with('/'))
+            .map(|entry| PathBuf::from(&entry.path))
+            .collect();
+        let mut stale = receipt
+            .as_ref()
+            .map(|receipt| receipt.exclusive_files(&package.name, &files))
+            .unwrap_or_default();
+        let modified = receipt
+            .as_ref()
+            .and_then(|receipt| receipt.get(&package.name))
+            .map(|installed| installed.modified_files(dir))
+            .unwrap_or_default();
+        stale.retain(|file| !modified.contains(file));
+        // the modified files left in place, and the ones added or modified that the new version overwrites
+        let mut preserved: Vec<Action> = modified
+            .iter()
+            .filter(|path| !files.contains(path))
+            .map(|path| Action::Preserve {
+                path: path.clone(),
+                backup: None,
+            })
+            .collect();
+        preserved.extend(
+            files
+                .iter()
+                .filter(|file| {
+                    fs::symlink_metadata(dir.join(file)).is_ok_and(|meta| !meta.is_dir())
+                        && receipt
+                            .as_ref()
+                            .is_none_or(|receipt| receipt.is_user_file(dir, file))
+                })
+                .map(|file| Action::Preserve {
+                    backup: Some(backup_path(dir, file)),
+                    path: file.clone(),
+                }),
+        );
+
+        plan.actions.push(Action::Install {
+            from: receipt
+                .as_ref()
+                .and_then(|receipt| receipt.get(&package.name))
+                .map(|installed| installed.version.clone()),
+            url: registry.file_url(&package.filepath_as(&version, archive.format)),
+            name: package.name,
+            branch: package.branch,
+            arch: package.arch,
+            version: version.version,
+            size: archive.size,
+            files,
+        });
+        plan.actions.extend(preserved);
+        plan.actions
+            .extend(stale.into_iter().map(|path| Action::Remove { path }));
+    }
+
+    Ok(plan)
+}
+
+/// Resolves the installation directory from `--dir`, `$GREYCAT_HOME` or `$HOME/.greycat`
+pub fn install_dir(dir: Option<PathBuf>) -> PathBuf {
+    dir.unwrap_or_else(|| {
+        std::env::var("GREYCAT_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| {
+                let mut home_dir = home::home_dir().unwrap_or_else(|| "/".into());
+                home_dir.push(".greycat");
+                home_dir
+            })
+    })
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "windows"))]
+pub fn get_arch() -> String {
+    "x64-windows".to_owned()
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "macos"))]
+pub fn get_arch() -> String {
+    "x64-apple".to_owned()
+}
+
+#[cfg(all(target_arch = "aarch64", target_os = "macos"))]
+pub fn get_arch() -> String {
+    "arm64-apple".to_owned()
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+pub fn get_arch() -> String {
+    "x64-linux".to_owned()
+}
@@ -0,0 +1,152 @@
+//! GreyCat Manager, the library behind the `gcm` command line.
+//!
+//! It lists what the GreyCat registry publishes and installs packages from it in a directory, keeping track of them
+//! in a receipt so that they can be updated and removed later on.
+//!
+//! ```no_run
+//! use gcm::{Installation, Package, Registry, Target};
+//!
+//! # fn main() -> gcm::Result<()> {
+//! for version in Registry::default().list_package_versions("core", "stable", Some(5))? {
+//!     println!("{version}");
+//! }
+//!
+//! let installation = Installation::new("/opt/greycat");
+//! installation.install(Target::defaults("stable"))?;
+//! installation.install(vec![Target::latest(Package::new("lang", Some("noarch".into()), "dev"))])?;
+//! for package in installation.list()? {
+//!     println!("{}@{} {}", package.name, package.branch, package.version);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only the items exported at the root of the crate are public, the `gcm` binary being built from the same
+//! internals.
+
+use std::path::{Path, PathBuf};
+
+mod archive;
+mod cache;
+#[doc(hidden)]
+pub mod cli;
+mod cmd;
+mod config;
+mod error;
+mod index;
+mod install;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+mod notice;
+mod observer;
+mod package;
+mod plan;
+mod progress;
+mod receipt;
+mod registry;
+mod reporter;
+mod shim;
+mod toolchain;
+
+pub use error::{Error, ErrorKind, Result};
+pub use install::Target;
+/// The arch the packages of the current platform are published for, eg. `x64-linux`
+pub use install::get_arch;
+pub use observer::{Observer, Skipped};
+pub use package::{Package, Version};
+pub use receipt::InstalledPackage;
+pub use registry::{File, PackageVersion, Registry};
+
+use receipt::Receipt;
+use toolchain::Toolchain;
+
+/// A directory GreyCat packages are installed in, eg. `$HOME/.greycat`.
+///
+/// Its `config.json` and `cache/` are the ones used, by its toolchains as well.
+pub struct Installation {
+    dir: PathBuf,
+    registry: Registry,
+    observer: Box<dyn Observer>,
+}
+
+impl std::fmt::Debug for Installation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Installation")
+            .field("dir", &self.dir)
+            .field("registry", &self.registry.url())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Installation {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            registry: Registry::default(),
+            observer: Box::new(()),
+        }
+    }
+
+    /// Sets the registry packages are installed from, [`Registry::default`] by default.
+    pub fn with_registry(mut self, registry: Registry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Sets what gets told about the progress of install, update and uninstall, nothing by default.
+    pub fn with_observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.observer = Box::new(observer);
+        self
+    }
+
+    /// The installation at `$GREYCAT_HOME`, or `$HOME/.greycat`
+    pub fn from_env() -> Self {
+        Self::new(install::install_dir(None))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The packages installed, empty when nothing has been installed yet
+    pub fn list(&self) -> Result<Vec<InstalledPackage>> {
+        let receipt = Receipt::load(&self.dir)?;
+        Ok(receipt.map(|receipt| receipt.packages).unwrap_or_default())
+    }
+
+    /// Installs `targets`, replacing the files of their previously installed versions, and returns every package
+    /// installed afterwards.
+    pub fn install(&self, targets: Vec<Target>) -> Result<Vec<InstalledPackage>> {
+        let receipt =
+            install::install_targets(&self.dir, targets, &self.registry, self.observer.as_ref())?;
+        Ok(receipt.packages)
+    }
+
+    /// Updates every installed package to the latest version of its branch allowed by its pin, and returns every
+    /// package installed afterwards.
+    pub fn update(&self) -> Result<Vec<InstalledPackage>> {
+        let Some(receipt) = Receipt::load(&self.dir)? else {
+            return Ok(Vec::new());
+        };
+        let targets = install::resolve_updates(&receipt, &self.registry, self.observer.as_ref())?;
+        if targets.is_empty() {
+            return Ok(receipt.packages);
+        }
+        self.install(targets)
+    }
+
+    /// Removes the packages `names`, every installed package when empty, leaving the files modified since they were
+    /// installed in place.
+    pub fn uninstall(&self, names: &[String]) -> Result<()> {
+        let (plan, receipt) = install::plan_uninstall(&self.dir, names)?;
+        install::uninstall(&plan, receipt, self.observer.as_ref())?;
+        Ok(())
+    }
+
+    /// The directory of the installation of `toolchain`, eg. `dev` or `7.0`, installing it next to this one when
+    /// there is none.
+    pub fn resolve(&self, toolchain: &str) -> Result<PathBuf> {
+        let toolchain: Toolchain = toolchain.parse()?;
+        Ok(toolchain.resolve(&self.dir, &self.registry, self.observer.as_ref())?)
+    }
+}
@@ -0,0 +1,380 @@
+//! Async versions of [`Registry`](crate::Registry) and [`Installation`](crate::Installation), for tokio based
+//! embedders.
+//!
+//! Only the requests to the registry are async, the resolution and extraction logic being the one of the blocking
+//! API: archives are downloaded first, then extracted on the blocking thread pool of tokio.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+use tokio::io::AsyncWriteExt;
+
+use crate::Result;
+use crate::archive::Format;
+use crate::config::Config;
+use crate::install::{self, Available, Target};
+use crate::observer::Observer;
+use crate::package::{self, Download, Package, Spooled};
+use crate::receipt::{InstalledPackage, Receipt};
+use crate::registry::{self, File, Listing, PackageVersion};
+use crate::toolchain::{Toolchain, home_of};
+
+/// The async counterpart of [`crate::Registry`]
+#[derive(Debug, Clone)]
+pub struct Registry {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl Default for Registry {
+    /// The registry at `$GCM_REGISTRY`, or the official one at `https://get.greycat.io/files`
+    fn default() -> Self {
+        Self::new(crate::Registry::default().url())
+    }
+}
+
+impl Registry {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: crate::Registry::new(url).url().to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub async fn list_package_versions(
+        &self,
+        name: &str,
+        branch: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<PackageVersion>> {
+        let entries: Vec<File> = self
+            .get_json(&format!("{name}/{branch}/"))
+            .await
+            .with_context(|| format!("no version found for \"{name}/{branch}\""))?;
+
+        let mut versions = Vec::default();
+        for file in entries.iter().filter(|file| file.path.ends_with('/')) {
+            let targets: Vec<File> = self
+                .get_json(&file.path)
+                .await
+                .with_context(|| format!("no version found for \"{name}/{branch}\""))?;
+            for dir in targets.iter().filter(|file| file.path.ends_with('/')) {
+                let files: Vec<File> = self.get_json(&dir.path).await?;
+                versions.extend(registry::archive_versions(&files));
+            }
+            versions.extend(registry::archive_versions(&targets));
+        }
+
+        Ok(registry::latest_versions(versions, limit))
+    }
+
+    /// Lists the content of a registry directory, eg. `core/stable/7.0/`
+    pub async fn list_files(&self, path: &str) -> Result<Vec<File>> {
+        let files = self
+            .get_json(path)
+            .await
+            .with_context(|| format!("unable to list \"{path}\""))?;
+        Ok(files)
+    }
+
+    pub async fn list_package_branches(&self, name: &str) -> Result<Vec<File>> {
+        Ok(self.get_json(&format!("{name}/")).await?)
+    }
+
+    pub async fn list_packages(&self) -> Result<Vec<File>> {
+        let files: Vec<File> = self.get_json("").await?;
+
+        let mut packages = Vec::default();
+        for file in files {
+            match Listing::of(&file) {
+                Listing::Package => packages.push(file),
+                Listing::Ignored => (),
+                Listing::Group => packages.extend(self.get_json::<Vec<File>>(&file.path).await?),
+            }
+        }
+
+        packages.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(packages)
+    }
+
+    /// The latest version of `package`, as published in `<name>/<branch>/latest`
+    pub async fn latest(&self, package: &Package) -> Result<package::Version> {
+        let latest = self
+            .get(&format!("{}/{}/latest", package.name, package.branch))
+            .await?
+            .text()
+            .await
+            .map_err(anyhow::Error::from)?;
+        package::Version::try_from(latest)
+    }
+
+    /// Downloads the archive of `version` to the cache of `home`, `None` when it is not published.
+    ///
+    /// The download fails once over `limit` bytes.
+    async fn fetch(
+        &self,
+        home: &Path,
+        package: &Package,
+        version: &package::Version,
+        limit: u64,
+        observer: &(dyn Observer + Send + Sync),
+    ) -> anyhow::Result<Option<Spooled>> {
+        let format = match self.list_files(&package.dirpath(version)).await {
+            Ok(files) => package.preferred_format(version, &files),
+            Err(_) => Format::Zip,
+        };
+        let res = match self.get(&package.filepath_as(version, format)).await {
+            Ok(res) => res,
+            Err(err) if package::is_not_found(&err) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let content_type = res
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        let mut download = Download::new(
+            package,
+            version,
+            format,
+            content_type,
+            res.content_length(),
+            limit,
+        )?;
+
+        let filepath = download.filepath.clone();
+        let archive = package.spooled(home, version, download.format)?;
+        let mut file = tokio::fs::File::create(&archive.path)
+            .await
+            .with_context(|| format!("downloading {filepath}"))?;
+        let mut stream = res.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.with_context(|| format!("downloading {filepath}"))?;
+            file.write_all(&chunk)
+                .await
+                .with_context(|| format!("downloading {filepath}"))?;
+            download.add(chunk.len())?;
+            observer.download_progress(package, download.downloaded, download.total);
+        }
+        file.flush()
+            .await
+            .with_context(|| format!("downloading {filepath}"))?;
+        Ok(Some(archive))
+    }
+
+    async fn get(&self, path: &str) -> anyhow::Result<reqwest::Response> {
+        let res = self
+            .client
+            .get(format!("{}/{path}", self.url))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(res)
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        Ok(self.get(path).await?.json().await?)
+    }
+}
+
+/// The async counterpart of [`crate::Installation`]
+#[derive(Clone)]
+pub struct Installation {
+    dir: PathBuf,
+    registry: Registry,
+    observer: Arc<dyn Observer + Send + Sync>,
+}
+
+impl std::fmt::Debug for Installation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Installation")
+            .field("dir", &self.dir)
+            .field("registry", &self.registry.url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Installation {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            registry: Registry::default(),
+            observer: Arc::new(()),
+        }
+    }
+
+    /// The installation at `$GREYCAT_HOME`, or `$HOME/.greycat`
+    pub fn from_env() -> Self {
+        Self::new(install::install_dir(None))
+    }
+
+    /// Sets the registry packages are installed from, [`Registry::default`] by default.
+    pub fn with_registry(mut self, registry: Registry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Sets what gets told about the progress of install, update and uninstall, nothing by default.
+    ///
+    /// Since extraction runs on another thread, the observer is called from there as well.
+    pub fn with_observer(mut self, observer: impl Observer + Send + Sync + 'static) -> Self {
+        self.observer = Arc::new(observer);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The packages installed, empty when nothing has been installed yet
+    pub async fn list(&self) -> Result<Vec<InstalledPackage>> {
+        let dir = self.dir.clone();
+        let receipt = blocking(move || Receipt::load(&dir)).await?;
+        Ok(receipt.map(|receipt| receipt.packages).unwrap_or_default())
+    }
+
+    /// Installs `targets`, replacing the files of their previously installed versions, and returns every package
+    /// installed afterwards.
+    ///
+    /// Every archive is downloaded before the first one gets extracted.
+    pub async fn install(&self, targets: Vec<Target>) -> Result<Vec<InstalledPackage>> {
+        let receipt = self.install_in(self.dir.clone(), targets).await?;
+        Ok(receipt.packages)
+    }
+
+    /// Updates every installed package to the latest version of its branch allowed by its pin, and returns every
+    /// package installed afterwards.
+    pub async fn update(&self) -> Result<Vec<InstalledPackage>> {
+        let dir = self.dir.clone();
+        let Some(receipt) = blocking(move || Receipt::load(&dir)).await? else {
+            return Ok(Vec::new());
+        };
+
+        let mut targets = Vec::new();
+        for installed in &receipt.packages {
+            let package = installed.package();
+            self.observer.resolving(&package);
+            let available = match &installed.pin {
+                Some(_) => Available::Versions(
+                    self.registry
+                        .list_package_versions(&installed.name, &installed.branch, None)
+                        .await?,
+                ),
+                None => {
+                    Available::Latest(install::latest_found(self.registry.latest(&package).await)?)
+                }
+            };
+            targets.extend(install::update_target(
+                installed,
+                available,
+                self.observer.as_ref(),
+            )?);
+        }
+
+        if targets.is_empty() {
+            return Ok(receipt.packages);
+        }
+        self.install(targets).await
+    }
+
+    /// Removes the packages `names`, every installed package when empty, leaving the files modified since they were
+    /// installed in place.
+    pub async fn uninstall(&self, names: &[String]) -> Result<()> {
+        let dir = self.dir.clone();
+        let names = names.to_vec();
+        let observer = self.observer.clone();
+        blocking(move || {
+            let (plan, receipt) = install::plan_uninstall(&dir, &names)?;
+            install::uninstall(&plan, receipt, observer.as_ref())
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// The directory of the installation of `toolchain`, eg. `dev` or `7.0`, installing it next to this one when
+    /// there is none.
+    pub async fn resolve(&self, toolchain: &str) -> Result<PathBuf> {
+        let toolchain: Toolchain = toolchain.parse()?;
+        let home = self.dir.clone();
+        let found = {
+            let toolchain = toolchain.clone();
+            blocking(move || toolchain.find(&home)).await?
+        };
+        if let Some(dir) = found {
+            return Ok(dir);
+        }
+
+        let versions = match &toolchain {
+            Toolchain::Branch(_) => Vec::new(),
+            Toolchain::Version { branch, .. } => {
+                self.registry
+                    .list_package_versions("core", branch, None)
+                    .await?
+            }
+        };
+        let (dir, targets) = toolchain.targets(&self.dir, versions)?;
+        self.observer.message(&format_args!(
+            "toolchain '{toolchain}' is not installed, installing it in {}",
+            dir.display()
+        ));
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| format!("creating {}", dir.display()))?;
+        let installed = self.install_in(dir.clone(), targets).await;
+        Ok(toolchain.installed(dir, installed)?)
+    }
+
+    /// Resolves and downloads `targets`, then installs them in `dir` as [`install::install_targets`] does.
+    async fn install_in(&self, dir: PathBuf, targets: Vec<Target>) -> anyhow::Result<Receipt> {
+        let observer = self.observer.as_ref();
+        let home = home_of(&dir).to_path_buf();
+        // the archives are bounded by what they may extract
+        let limit = blocking(move || Config::load(&home)).await?;
+        let limit = limit.extract_limits.max_total_bytes;
+        let mut resolved = Vec::new();
+        for target in targets {
+            if target.version.is_some() {
+                resolved.push(target);
+                continue;
+            }
+            observer.resolving(&target.package);
+            let found = install::latest_found(self.registry.latest(&target.package).await);
+            resolved.extend(install::resolve_target(target, found, observer)?);
+        }
+
+        let mut fetched = HashMap::new();
+        for target in &resolved {
+            if let Some(version) = &target.version {
+                let version = package::Version::from(version);
+                let archive = self
+                    .registry
+                    .fetch(home_of(&dir), &target.package, &version, limit, observer)
+                    .await;
+                fetched.insert(target.package.name.clone(), archive);
+            }
+        }
+
+        let observer = self.observer.clone();
+        blocking(move || {
+            install::install_fetched(&dir, resolved, observer.as_ref(), |package, _| {
+                fetched.remove(&package.name).unwrap_or(Ok(None))
+            })
+        })
+        .await
+    }
+}
+
+/// Runs `f` on the blocking thread pool of tokio
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    tokio::task::spawn_blocking(f).await?
+}
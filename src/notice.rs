@@ -0,0 +1,126 @@
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::cache;
+use crate::config::Config;
+use crate::install::install_dir;
+use crate::receipt::Receipt;
+use crate::reporter::Reporter;
+
+const CACHE_ENTRY: &str = "update-check.json";
+
+/// How long the check may delay the command `gcm run` and the shims get replaced by
+const EXEC_WAIT: Duration = Duration::from_secs(1);
+
+/// Only the packages worth bothering the user about
+const WATCHED: [&str; 2] = ["core", "lang"];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Latest {
+    name: String,
+    branch: String,
+    version: String,
+}
+
+fn enabled(config: &Config) -> bool {
+    config.update_check.enabled
+        && std::env::var_os("GCM_NO_UPDATE_CHECK").is_none_or(|v| v.is_empty())
+}
+
+/// Refreshes the cached latest versions of the installed core and lang in a background thread,
+/// at most once per `update_check.interval_hours`.
+///
+/// The thread is never waited for: if gcm exits first, the next invocation will try again.
+pub fn spawn_check() -> Option<JoinHandle<()>> {
+    let home = install_dir(None);
+    let config = Config::load(&home).ok()?;
+    if !enabled(&config) {
+        return None;
+    }
+    let ttl = chrono::Duration::hours(config.update_check.interval_hours as i64);
+    if cache::read::<Vec<Latest>>(&home, CACHE_ENTRY).is_some_and(|cached| cached.is_fresh(ttl)) {
+        return None;
+    }
+    let receipt = Receipt::load(&home).ok()??;
+
+    let packages: Vec<_> = receipt
+        .packages
+        .iter()
+        .filter(|p| WATCHED.contains(&p.name.as_str()))
+        .map(|p| p.package())
+        .collect();
+    if packages.is_empty() {
+        return None;
+    }
+
+    Some(std::thread::spawn(move || {
+        let latest: Vec<Latest> = packages
+            .iter()
+            .filter_map(|package| {
+                let latest = package.latest().ok()?;
+                Some(Latest {
+                    name: package.name.clone(),
+                    branch: package.branch.clone(),
+                    version: latest.version,
+                })
+            })
+            .collect();
+        if !latest.is_empty() {
+            cache::write(&home, CACHE_ENTRY, latest).ok();
+        }
+    }))
+}
+
+/// [`spawn_check`] then [`print_hints`], right before gcm gets replaced by the command it runs.
+///
+/// Nothing is left to complete the check in the background once replaced, it gets [`EXEC_WAIT`] to do so.
+pub fn check_before_exec(reporter: &Reporter) {
+    if let Some(check) = spawn_check() {
+        let deadline = Instant::now() + EXEC_WAIT;
+        while !check.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+    print_hints(reporter);
+}
+
+/// Prints a one-line hint for every installed core or lang that has a newer version on its branch its pin allows,
+/// based on what the last check cached.
+pub fn print_hints(reporter: &Reporter) {
+    hints(reporter).ok();
+}
+
+fn hints(reporter: &Reporter) -> Result<()> {
+    let home = install_dir(None);
+    if !enabled(&Config::load(&home)?) {
+        return Ok(());
+    }
+    let Some(cached) = cache::read::<Vec<Latest>>(&home, CACHE_ENTRY) else {
+        return Ok(());
+    };
+    let Some(receipt) = Receipt::load(&home)? else {
+        return Ok(());
+    };
+
+    for latest in &cached.value {
+        let Some(installed) = receipt.get(&latest.name) else {
+            continue;
+        };
+        if installed.branch != latest.branch {
+            continue;
+        }
+        let version = semver::Version::parse(&latest.version)?;
+        // 'gcm update' leaves the pinned packages as they are
+        if installed.allows(&version) && version > semver::Version::parse(&installed.version)? {
+            reporter.note(format_args!(
+                "{}@{} {} is available (installed {}), use 'gcm update'",
+                latest.name, latest.branch, latest.version, installed.version
+            ));
+        }
+    }
+
+    Ok(())
+}
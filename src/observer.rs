@@ -0,0 +1,59 @@
+use std::fmt::Display;
+use std::path::Path;
+
+use crate::package::Package;
+
+/// Why a package has not been installed
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Skipped<'a> {
+    /// Neither the package nor the requested version of it is published
+    NotFound,
+    /// Installing an optional package, eg. `explorer` along with `core`, failed
+    Failed(&'a (dyn std::error::Error + Send + Sync + 'static)),
+}
+
+/// Receives what install, update and uninstall are doing, eg. to display their progress.
+///
+/// Every method does nothing by default, `()` being the observer ignoring everything.
+#[allow(unused_variables)]
+pub trait Observer {
+    /// The latest version of `package` is being looked up in the registry, before it gets installed or updated.
+    fn resolving(&self, package: &Package) {}
+
+    /// Starts downloading the archive of `package` to list what installing it would do, followed by either
+    /// [`planned`](Observer::planned) or [`skipped`](Observer::skipped) unless it fails.
+    fn planning(&self, package: &Package) {}
+
+    /// Installing `version` of `package` has been planned, without changing anything.
+    fn planned(&self, package: &Package, version: &str) {}
+
+    /// Starts the install of `package`, followed by either [`installed`](Observer::installed) or
+    /// [`skipped`](Observer::skipped) unless it fails.
+    fn installing(&self, package: &Package) {}
+
+    /// `downloaded` bytes of the archive of `package` have been received, out of `total` when the registry tells.
+    fn download_progress(&self, package: &Package, downloaded: u64, total: Option<u64>) {}
+
+    /// `path`, relative to the installation directory, is the `count`th entry extracted out of `total`.
+    fn file_extracted(&self, package: &Package, path: &Path, count: usize, total: usize) {}
+
+    fn installed(&self, package: &Package, version: &str) {}
+
+    fn skipped(&self, package: &Package, reason: Skipped<'_>) {}
+
+    /// Starts removing the files of `package`.
+    fn uninstalling(&self, package: &Package, version: &str) {}
+
+    /// `path` has been modified since it was installed and is left as is, or was added or modified by the user and
+    /// moved to `backup` when a package overwrote it.
+    fn kept(&self, path: &Path, backup: Option<&Path>) {}
+
+    /// Something worth telling, eg. where a missing toolchain gets installed
+    fn message(&self, msg: &dyn Display) {}
+
+    /// Something went wrong without failing the operation, eg. updating the shims
+    fn warning(&self, msg: &dyn Display) {}
+}
+
+impl Observer for () {}
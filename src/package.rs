@@ -1,15 +1,36 @@
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
-use std::io::{self, ErrorKind};
-use std::path::Path;
-
-use anyhow::{bail, Context, Result};
-use zip::{
-    read::ZipFile,
-    result::{ZipError, ZipResult},
-    unstable::stream::{ZipStreamFileMetadata, ZipStreamReader, ZipStreamVisitor},
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::archive::{
+    Archive, Entry, EntryKind, FORMATS, Format, InvalidArchive, enclosed_name, symlink_enclosed,
 };
+use crate::cache;
+use crate::config::{Config, ExtractLimits};
+use crate::error::kind_of;
+use crate::observer::Observer;
+use crate::progress::FmtBytes;
+use crate::registry::{File, Registry};
+
+/// Where archives are downloaded to in the cache, before being extracted
+const DOWNLOADS_DIR: &str = "downloads";
+
+/// Where archives are extracted in the installation directory, before being moved in place
+const STAGING_DIR: &str = ".gcm-staging";
+
+/// Appended to the name of a file the user modified or added, to keep it when a package overwrites it
+const BACKUP_SUFFIX: &str = ".gcm-bak";
+
+/// Small entries may legitimately compress very well, eg. a file full of zeros
+const RATIO_MIN_BYTES: u64 = 1024 * 1024;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct Package {
     pub name: String,
     pub arch: Option<String>,
@@ -25,96 +46,108 @@ impl Package {
         }
     }
 
-    pub fn install(&self, version: semver::Version, install_dir: &Path) -> Result<Option<Version>> {
-        let g_version = Version {
-            major_minor: format!("{}.{}", version.major, version.minor),
-            version: version.to_string(),
-        };
-
-        match self.download(&g_version) {
-            Ok(archive) => {
-                SmartZipExtractor { reader: archive }
-                    .smart_extract(install_dir)
-                    .context("extracting package content")?;
-                Ok(Some(g_version))
-            }
-            Err(_) => Ok(None),
+    /// Extracts the downloaded `archive` of `version` in `install_dir`, once validated against the limits of
+    /// `config`, backing up the files it overwrites for which `keep` is true.
+    ///
+    /// The `legacy` directories, left by an installation without receipt, are replaced as a whole.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn install_from(
+        &self,
+        archive: Spooled,
+        version: Version,
+        install_dir: &Path,
+        keep: &dyn Fn(&Path) -> bool,
+        legacy: &[&str],
+        config: &Config,
+        observer: &dyn Observer,
+    ) -> Result<Installed> {
+        let format = archive.format;
+        let file = fs::File::open(&archive.path)
+            .with_context(|| format!("opening {}", archive.path.display()))?;
+        let size = file.metadata()?.len();
+        let reader = format
+            .open(io::BufReader::new(file))
+            .with_context(|| format!("reading package archive ({format})"))?;
+        let extracted = SmartExtractor {
+            archive: reader,
+            size,
+            package: self,
+            observer,
+            limits: config.extract_limits.clone(),
+            keep_special_mode_bits: config.keep_special_mode_bits,
         }
-    }
-
-    pub fn install_latest(&self, install_dir: &Path) -> Result<Option<Version>> {
-        use std::io::Write;
-        use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
-
-        let latest = match self.latest() {
-            Ok(latest) => latest,
-            Err(_) => {
-                let mut stderr = StandardStream::stderr(ColorChoice::AlwaysAnsi);
-                stderr.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
-                writeln!(&mut stderr, "not found")?;
-                stderr.reset()?;
-                return Ok(None);
-            }
-        };
+        .smart_extract(install_dir, keep, legacy)
+        .context("extracting package content")?;
 
-        let version = semver::Version::parse(&latest.version)?;
-        match self.install(version, install_dir)? {
-            Some(version) => {
-                let mut stderr = StandardStream::stderr(ColorChoice::AlwaysAnsi);
-                stderr.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
-                writeln!(&mut stderr, "{}", version.version)?;
-                stderr.reset()?;
-                Ok(Some(version))
-            }
-            None => {
-                let mut stderr = StandardStream::stderr(ColorChoice::AlwaysAnsi);
-                stderr.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
-                writeln!(&mut stderr, "not found")?;
-                stderr.reset()?;
-                Ok(None)
-            }
-        }
+        observer.installed(self, &version.version);
+        Ok(Installed {
+            version,
+            files: extracted.files,
+            hashes: extracted.hashes,
+            backups: extracted.backups,
+        })
     }
 
-    pub fn latest(&self) -> Result<Version> {
-        let url = format!(
-            "https://get.greycat.io/files/{}/{}/latest",
-            self.name, self.branch
-        );
-        let latest: String = ureq::get(&url).call()?.into_string()?;
-        let latest = Version::try_from(latest)?;
-        Ok(latest)
+    /// The latest version of this package in the default registry, see [`Registry::latest`]
+    pub fn latest(&self) -> crate::Result<Version> {
+        Registry::default().latest(self)
     }
 
-    pub fn download(
-        &self,
-        version: &Version,
-    ) -> Result<ZipStreamReader<Box<dyn std::io::Read + std::marker::Send + std::marker::Sync>>>
-    {
-        let filepath = match &self.arch {
+    /// The directory of the registry holding the archives of `version`
+    pub(crate) fn dirpath(&self, version: &Version) -> String {
+        match &self.arch {
             Some(arch) => format!(
-                "{name}/{branch}/{major_minor}/{arch}/{version}.zip",
+                "{name}/{branch}/{major_minor}/{arch}/",
                 name = self.name,
                 branch = self.branch,
                 major_minor = version.major_minor,
-                version = version.version
             ),
             None => format!(
-                "{name}/{branch}/{major_minor}/{version}.zip",
+                "{name}/{branch}/{major_minor}/",
                 name = self.name,
                 branch = self.branch,
                 major_minor = version.major_minor,
-                version = version.version
             ),
-        };
-
-        let url = format!("https://get.greycat.io/files/{filepath}");
-        let res = ureq::get(&url).call()?;
-        if res.status() != 200 {
-            bail!("unable to download {filepath}")
         }
+    }
+
+    pub(crate) fn filepath_as(&self, version: &Version, format: Format) -> String {
+        format!(
+            "{}{}{}",
+            self.dirpath(version),
+            version.version,
+            format.extension()
+        )
+    }
+
+    /// The preferred format among those `files`, the content of [`dirpath`](Package::dirpath), has `version` in.
+    pub(crate) fn preferred_format(&self, version: &Version, files: &[File]) -> Format {
+        FORMATS
+            .into_iter()
+            .find(|format| {
+                let filepath = self.filepath_as(version, *format);
+                files.iter().any(|file| file.path == filepath)
+            })
+            .unwrap_or(Format::Zip)
+    }
 
-        Ok(ZipStreamReader::new(res.into_reader()))
+    /// Where to download the archive of `version` in the cache of `home`, removed once dropped
+    pub(crate) fn spooled(
+        &self,
+        home: &Path,
+        version: &Version,
+        format: Format,
+    ) -> Result<Spooled> {
+        let dir = cache::dir(home).join(DOWNLOADS_DIR);
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        // concurrent gcm invocations may download the same archive
+        let filepath = self.filepath_as(version, format);
+        let path = dir.join(format!(
+            "{}.{}",
+            filepath.replace('/', "_"),
+            std::process::id()
+        ));
+        Ok(Spooled { path, format })
     }
 }
 
@@ -125,6 +158,7 @@ impl std::fmt::Display for Package {
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct Version {
     pub major_minor: String,
     pub version: String,
@@ -136,93 +170,852 @@ impl std::fmt::Display for Version {
     }
 }
 
+impl From<&semver::Version> for Version {
+    fn from(version: &semver::Version) -> Self {
+        Version {
+            major_minor: format!("{}.{}", version.major, version.minor),
+            version: version.to_string(),
+        }
+    }
+}
+
 impl TryFrom<String> for Version {
-    type Error = anyhow::Error;
+    type Error = crate::Error;
 
-    fn try_from(value: String) -> std::prelude::v1::Result<Self, Self::Error> {
+    fn try_from(value: String) -> crate::Result<Self> {
         match value.split_once('/') {
             Some((major_minor, version)) => Ok(Version {
                 major_minor: major_minor.to_owned(),
                 version: version.to_owned(),
             }),
-            None => bail!("invalid version \"{value}\""),
+            None => Err(anyhow::anyhow!("invalid version \"{value}\"").into()),
         }
     }
 }
 
-struct SmartZipExtractor<R> {
-    reader: ZipStreamReader<R>,
+/// Downloads the archive of `package` at `version` to the cache of `home`, for its entries to be read before
+/// extracting anything, `None` when it is not published.
+///
+/// The download fails once over `limit` bytes.
+pub(crate) fn fetch(
+    registry: &Registry,
+    home: &Path,
+    package: &Package,
+    version: &Version,
+    limit: u64,
+    observer: &dyn Observer,
+) -> Result<Option<Spooled>> {
+    let format = format(registry, package, version);
+    let mut reader = match download(registry, package, version, format, limit, observer) {
+        Ok(reader) => reader,
+        Err(err) if is_not_found(&err) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let archive = package.spooled(home, version, reader.download.format)?;
+    let res = fs::File::create(&archive.path).and_then(|mut file| io::copy(&mut reader, &mut file));
+    res.with_context(|| format!("downloading {}", reader.download.filepath))?;
+    Ok(Some(archive))
 }
 
-impl<R: io::Read> SmartZipExtractor<R> {
-    fn smart_extract<P: AsRef<Path>>(self, directory: P) -> ZipResult<()> {
-        struct Extractor<'a>(&'a Path);
-        impl ZipStreamVisitor for Extractor<'_> {
-            fn visit_file(&mut self, file: &mut ZipFile<'_>) -> ZipResult<()> {
-                let filepath = file
-                    .enclosed_name()
-                    .ok_or(ZipError::InvalidArchive("Invalid file path"))?;
+/// Whether a request failed because the registry does not have what was asked for
+pub(crate) fn is_not_found(err: &anyhow::Error) -> bool {
+    kind_of(err) == crate::ErrorKind::NotFound
+}
+
+/// The preferred format `version` of `package` is published in, zip when the registry cannot tell.
+pub fn format(registry: &Registry, package: &Package, version: &Version) -> Format {
+    match registry.list_files(&package.dirpath(version)) {
+        Ok(files) => package.preferred_format(version, &files),
+        Err(_) => Format::Zip,
+    }
+}
 
-                let outpath = self.0.join(filepath);
+/// Downloads the archive of `version` in `format`, along with the format it turns out to be in.
+///
+/// Reading it fails once over `limit` bytes.
+pub fn open<'a>(
+    registry: &Registry,
+    package: &'a Package,
+    version: &Version,
+    format: Format,
+    limit: u64,
+    observer: &'a dyn Observer,
+) -> Result<(impl io::Read + 'a, Format)> {
+    let reader = download(registry, package, version, format, limit, observer)?;
+    let format = reader.download.format;
+    Ok((reader, format))
+}
 
-                if file.name().ends_with('/') {
-                    fs::create_dir_all(&outpath)?;
-                } else {
-                    if let Some(p) = outpath.parent() {
-                        fs::create_dir_all(p)?;
-                    }
-                    match fs::File::create(&outpath) {
-                        Ok(mut outfile) => {
-                            io::copy(file, &mut outfile)?;
-                        }
-                        Err(err) if err.kind() == ErrorKind::ExecutableFileBusy => {
-                            // if we have greycat running, we need to be smarter
-                            // lets read the permissions of the current executable file
-                            let permissions = fs::File::open(&outpath)?.metadata()?.permissions();
-                            // remove the current executable file
-                            fs::remove_file(&outpath)?;
-                            // re-create it
-                            let mut outfile = fs::File::create(&outpath)?;
-                            // set the same permissions back
-                            outfile.set_permissions(permissions)?;
-                            // and then copy the content of the zipfile to the new executable file output
-                            io::copy(file, &mut outfile)?;
-                        }
-                        Err(err) => return Err(ZipError::Io(err)),
-                    }
+/// Requests the archive of `version` in `format`, telling `observer` how much of it has been read.
+fn download<'a>(
+    registry: &Registry,
+    package: &'a Package,
+    version: &Version,
+    format: Format,
+    limit: u64,
+    observer: &'a dyn Observer,
+) -> Result<DownloadReader<'a>> {
+    let filepath = package.filepath_as(version, format);
+    let res = ureq::get(&registry.file_url(&filepath)).call()?;
+    if res.status() != 200 {
+        bail!("unable to download {filepath}")
+    }
+
+    let total = res
+        .header("Content-Length")
+        .and_then(|len| len.parse::<u64>().ok());
+    let download = Download::new(
+        package,
+        version,
+        format,
+        res.header("Content-Type"),
+        total,
+        limit,
+    )?;
+    Ok(DownloadReader {
+        inner: res.into_reader(),
+        download,
+        package,
+        observer,
+    })
+}
+
+/// An archive being downloaded, whatever the HTTP client
+pub(crate) struct Download {
+    /// The format of the archive, as served by the registry
+    pub(crate) format: Format,
+    /// Where the archive is in the registry, with the extension of its `format`
+    pub(crate) filepath: String,
+    /// The size announced by the registry, if any
+    pub(crate) total: Option<u64>,
+    pub(crate) downloaded: u64,
+    limit: u64,
+}
+
+impl Download {
+    /// Checks what the registry answered to the request of the archive of `version` in `format`, refusing archives
+    /// announced over `limit` bytes.
+    pub(crate) fn new(
+        package: &Package,
+        version: &Version,
+        format: Format,
+        content_type: Option<&str>,
+        total: Option<u64>,
+        limit: u64,
+    ) -> io::Result<Self> {
+        // the content type wins over the extension
+        let format = content_type
+            .and_then(Format::from_content_type)
+            .unwrap_or(format);
+        let download = Self {
+            format,
+            filepath: package.filepath_as(version, format),
+            total,
+            downloaded: 0,
+            limit,
+        };
+        if total.is_some_and(|total| total > limit) {
+            return Err(download.limit_exceeded());
+        }
+        Ok(download)
+    }
+
+    /// Accounts for `n` more bytes, whatever the registry announced, failing once over the limit.
+    pub(crate) fn add(&mut self, n: usize) -> io::Result<()> {
+        self.downloaded += n as u64;
+        if self.downloaded > self.limit {
+            return Err(self.limit_exceeded());
+        }
+        Ok(())
+    }
+
+    /// The limit is the `max_total_bytes` of [`ExtractLimits`]
+    fn limit_exceeded(&self) -> io::Error {
+        InvalidArchive(format!(
+            "download of {} stopped: max_total_bytes exceeded (over {})",
+            self.filepath,
+            FmtBytes(self.limit)
+        ))
+        .into()
+    }
+}
+
+/// Downloads the archive of `version` in memory and lists its entries, without extracting anything.
+///
+/// The download fails once over `limit` bytes.
+pub fn inspect(
+    registry: &Registry,
+    package: &Package,
+    version: &Version,
+    limit: u64,
+    observer: &dyn Observer,
+) -> Result<ArchiveInfo> {
+    let format = format(registry, package, version);
+    let mut reader = download(registry, package, version, format, limit, observer)?;
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("downloading {}", reader.download.filepath))?;
+    let (format, filepath) = (reader.download.format, reader.download.filepath);
+
+    let sha256 = format!("{:x}", Sha256::digest(&bytes));
+    let size = bytes.len() as u64;
+
+    let entries = format
+        .open(io::Cursor::new(bytes))
+        .and_then(|mut archive| archive.entries())
+        .with_context(|| format!("reading {filepath}"))?
+        .into_iter()
+        .map(|entry| ArchiveEntry {
+            path: entry.name,
+            size: entry.size,
+            compressed_size: entry.compressed_size,
+            mode: entry.mode,
+        })
+        .collect();
+
+    Ok(ArchiveInfo {
+        format,
+        size,
+        sha256,
+        entries,
+    })
+}
+
+/// An archive downloaded to the cache, removed once dropped
+pub(crate) struct Spooled {
+    pub(crate) path: PathBuf,
+    pub(crate) format: Format,
+}
+
+impl Drop for Spooled {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// The outcome of a successful install of a package
+#[derive(Debug)]
+pub struct Installed {
+    pub version: Version,
+    /// The files written, relative to the installation directory
+    pub files: Vec<PathBuf>,
+    /// The sha256 of the regular files written
+    pub hashes: BTreeMap<PathBuf, String>,
+    /// The files overwritten that had to be kept, along with where they have been moved to
+    pub backups: Vec<(PathBuf, PathBuf)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveInfo {
+    pub format: Format,
+    pub size: u64,
+    pub sha256: String,
+    pub entries: Vec<ArchiveEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compressed_size: Option<u64>,
+    pub mode: Option<u32>,
+}
+
+/// A name for the backup of `file`, relative to `dir`, that does not exist yet: `file` followed by
+/// `.gcm-bak`, then numbered.
+pub fn backup_path(dir: &Path, file: &Path) -> PathBuf {
+    let mut backup = file.as_os_str().to_owned();
+    backup.push(BACKUP_SUFFIX);
+    let mut n = 0;
+    let mut candidate = PathBuf::from(&backup);
+    while fs::symlink_metadata(dir.join(&candidate)).is_ok() {
+        n += 1;
+        let mut numbered = backup.clone();
+        numbered.push(format!(".{n}"));
+        candidate = PathBuf::from(numbered);
+    }
+    candidate
+}
+
+/// Creates (or truncates) `path`, even when it is an executable currently running.
+pub fn create_file(path: &Path) -> io::Result<fs::File> {
+    match fs::File::create(path) {
+        Ok(file) => Ok(file),
+        Err(err) if err.kind() == ErrorKind::ExecutableFileBusy => {
+            // if we have greycat running, we need to be smarter
+            // lets read the permissions of the current executable file
+            let permissions = fs::File::open(path)?.metadata()?.permissions();
+            // remove the current executable file
+            fs::remove_file(path)?;
+            // re-create it
+            let file = fs::File::create(path)?;
+            // set the same permissions back
+            file.set_permissions(permissions)?;
+            Ok(file)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// The [`ExtractLimits`] an archive went over, named as in `config.json`
+fn limit_exceeded(path: &Path, limit: &str, detail: impl std::fmt::Display) -> io::Error {
+    InvalidArchive(format!(
+        "extraction stopped at {}: {limit} exceeded ({detail})",
+        path.display()
+    ))
+    .into()
+}
+
+fn invalid_archive(msg: &str) -> io::Error {
+    InvalidArchive(format!("invalid archive: {msg}")).into()
+}
+
+struct SmartExtractor<'a> {
+    archive: Box<dyn Archive + 'a>,
+    /// The size of the archive itself, for the ratio of formats not compressing their entries on their own
+    size: u64,
+    package: &'a Package,
+    observer: &'a dyn Observer,
+    limits: ExtractLimits,
+    /// Whether the setuid, setgid and sticky bits of the entries are applied
+    keep_special_mode_bits: bool,
+}
+
+impl SmartExtractor<'_> {
+    /// Checks every entry against the limits from what the archive declares, along with its path and the target
+    /// of its symlinks, before anything is written.
+    fn validate(&mut self) -> io::Result<usize> {
+        let entries = self.archive.entries()?;
+        let limits = &self.limits;
+        if entries.len() as u64 > limits.max_entries {
+            return Err(limit_exceeded(
+                Path::new(""),
+                "max_entries",
+                format_args!("{} entries, over {}", entries.len(), limits.max_entries),
+            ));
+        }
+        let mut total = 0u64;
+        let mut paths = Vec::with_capacity(entries.len());
+        let mut symlinks = HashSet::new();
+        for entry in &entries {
+            let filepath =
+                enclosed_name(&entry.name).ok_or_else(|| invalid_archive("invalid file path"))?;
+            match (entry.kind, &entry.target) {
+                (EntryKind::Other, _) => {
+                    return Err(invalid_archive(
+                        "device, FIFO, socket or hard link entries are not supported",
+                    ));
+                }
+                (EntryKind::Symlink, Some(target)) if symlink_enclosed(&filepath, target) => {
+                    symlinks.insert(filepath.clone());
+                }
+                (EntryKind::Symlink, _) => {
+                    return Err(invalid_archive(
+                        "symlink pointing outside of the installation directory",
+                    ));
                 }
+                (EntryKind::File | EntryKind::Dir, _) => (),
+            }
+            check_size(
+                limits,
+                &filepath,
+                total,
+                entry.size,
+                entry.compressed_size,
+                self.size,
+            )?;
+            total += entry.size;
+            paths.push(filepath);
+        }
+        // whatever their order, no entry may be extracted through a symlink of the archive
+        for path in &paths {
+            if let Some(symlink) = path.ancestors().skip(1).find(|dir| symlinks.contains(*dir)) {
+                return Err(invalid_archive(&format!(
+                    "{} goes through the symlink {}",
+                    path.display(),
+                    symlink.display()
+                )));
+            }
+        }
+        Ok(entries.len())
+    }
+
+    /// Extracts every entry in `directory`, the files it overwrites for which `keep` is true being backed up, and
+    /// the `legacy` directories replaced.
+    ///
+    /// The archive is validated first, then extracted to a staging directory where every file is read back. Only
+    /// then are the files moved in place, so that an invalid archive leaves `directory` untouched.
+    fn smart_extract<P: AsRef<Path>>(
+        mut self,
+        directory: P,
+        keep: &dyn Fn(&Path) -> bool,
+        legacy: &[&str],
+    ) -> io::Result<Extracted> {
+        let len = self.validate()?;
+
+        let dir = directory.as_ref();
+        let staging = Staging::new(dir)?;
+        let mut extracted = Extracted::default();
+        let mut count = 0;
+        self.archive.visit(&mut |entry, content| {
+            extract_entry(
+                &staging.new,
+                entry,
+                content,
+                &self.limits,
+                self.size,
+                self.keep_special_mode_bits,
+                &mut extracted,
+            )?;
+            count += 1;
+            self.observer
+                .file_extracted(self.package, Path::new(&entry.name), count, len);
+            Ok(())
+        })?;
+        verify(&staging.new, &extracted.expected)?;
+        extracted.backups = staging.commit(dir, &extracted.dirs, &extracted.files, keep, legacy)?;
+        Ok(extracted)
+    }
+}
 
-                Ok(())
+/// Where an archive is extracted before being moved to the installation directory, removed once dropped
+struct Staging {
+    root: PathBuf,
+    /// The content of the archive
+    new: PathBuf,
+    /// The files of the installation directory replaced by the archive, until every file has been moved
+    old: PathBuf,
+    /// The directories of an installation without receipt, until every file has been moved
+    legacy: PathBuf,
+}
+
+impl Staging {
+    /// Creates the staging directory inside `dir`, for the files to be moved rather than copied.
+    fn new(dir: &Path) -> io::Result<Self> {
+        let root = dir.join(format!("{STAGING_DIR}-{}", std::process::id()));
+        // left behind by a previous gcm that got killed
+        fs::remove_dir_all(&root).ok();
+        let staging = Self {
+            new: root.join("new"),
+            old: root.join("old"),
+            legacy: root.join("legacy"),
+            root,
+        };
+        fs::create_dir_all(&staging.new)?;
+        Ok(staging)
+    }
+
+    /// Moves the extracted `dirs` and `files` to `dir`, returning the backups made of the files replaced for which
+    /// `keep` is true.
+    ///
+    /// The other files replaced, and the `legacy` directories, are moved aside first, and every one of them is put
+    /// back when moving fails.
+    fn commit(
+        &self,
+        dir: &Path,
+        dirs: &[PathBuf],
+        files: &[PathBuf],
+        keep: &dyn Fn(&Path) -> bool,
+        legacy: &[&str],
+    ) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+        // nothing tells what a previous install wrote there, the directories go as a whole to prevent ghost files
+        let mut replaced_dirs = Vec::new();
+        let res = legacy.iter().try_for_each(|subdir| {
+            let path = dir.join(subdir);
+            if fs::symlink_metadata(&path).is_err() {
+                return Ok(());
             }
+            fs::create_dir_all(&self.legacy)?;
+            fs::rename(&path, self.legacy.join(subdir))?;
+            replaced_dirs.push(*subdir);
+            Ok(())
+        });
+        let res = res.and_then(|()| self.move_in(dir, dirs, files, keep));
+        if res.is_err() {
+            for subdir in replaced_dirs {
+                let path = dir.join(subdir);
+                fs::remove_dir_all(&path).ok();
+                fs::rename(self.legacy.join(subdir), &path).ok();
+            }
+        }
+        res
+    }
 
-            #[allow(unused)]
-            fn visit_additional_metadata(
-                &mut self,
-                metadata: &ZipStreamFileMetadata,
-            ) -> ZipResult<()> {
-                #[cfg(unix)]
-                {
-                    let filepath = metadata
-                        .enclosed_name()
-                        .ok_or(ZipError::InvalidArchive("Invalid file path"))?;
-
-                    let outpath = self.0.join(filepath);
-
-                    use std::os::unix::fs::PermissionsExt;
-                    if let Some(mode) = metadata.unix_mode() {
-                        fs::set_permissions(outpath, fs::Permissions::from_mode(mode))?;
-                    }
+    /// The files part of [`Staging::commit`], putting back the files it replaced when moving fails.
+    fn move_in(
+        &self,
+        dir: &Path,
+        dirs: &[PathBuf],
+        files: &[PathBuf],
+        keep: &dyn Fn(&Path) -> bool,
+    ) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+        for path in dirs.iter().chain(files) {
+            check_parents(dir, path)?;
+        }
+        for subdir in dirs {
+            let path = dir.join(subdir);
+            fs::create_dir_all(&path)?;
+            fs::set_permissions(&path, fs::metadata(self.new.join(subdir))?.permissions())?;
+        }
+
+        let mut moved: Vec<(&PathBuf, Option<PathBuf>)> = Vec::new();
+        let mut seen = HashSet::new();
+        let res = files
+            .iter()
+            .filter(|file| seen.insert(*file))
+            .try_for_each(|file| {
+                let path = dir.join(file);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
                 }
+                let replaced = match fs::symlink_metadata(&path) {
+                    Ok(meta) if !meta.is_dir() && keep(file) => {
+                        let backup = backup_path(dir, file);
+                        fs::rename(&path, dir.join(&backup))?;
+                        Some(dir.join(backup))
+                    }
+                    Ok(meta) if !meta.is_dir() => {
+                        let old = self.old.join(file);
+                        if let Some(parent) = old.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::rename(&path, &old)?;
+                        Some(old)
+                    }
+                    _ => None,
+                };
+                moved.push((file, replaced));
+                // renaming over a running executable is fine, unlike writing to it
+                fs::rename(self.new.join(file), &path)
+            });
 
-                Ok(())
+        if let Err(err) = res {
+            for (file, replaced) in moved.into_iter().rev() {
+                let path = dir.join(file);
+                fs::remove_file(&path).ok();
+                if let Some(old) = replaced {
+                    fs::rename(old, &path).ok();
+                }
             }
+            return Err(err);
+        }
+        Ok(moved
+            .into_iter()
+            .filter_map(|(file, replaced)| {
+                let backup = replaced.filter(|replaced| !replaced.starts_with(&self.old))?;
+                Some((file.clone(), backup))
+            })
+            .collect())
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.root).ok();
+    }
+}
+
+fn extract_entry(
+    dir: &Path,
+    entry: &Entry,
+    content: &mut dyn io::Read,
+    limits: &ExtractLimits,
+    archive_size: u64,
+    keep_special_mode_bits: bool,
+    extracted: &mut Extracted,
+) -> io::Result<()> {
+    let filepath =
+        enclosed_name(&entry.name).ok_or_else(|| invalid_archive("invalid file path"))?;
+    check_parents(dir, &filepath)?;
+    let outpath = dir.join(&filepath);
+
+    match entry.kind {
+        EntryKind::Dir => {
+            fs::create_dir_all(&outpath)?;
+            extracted.dirs.push(filepath);
+            return apply_mode(
+                &outpath,
+                entry.mode.unwrap_or(0o755),
+                keep_special_mode_bits,
+            );
+        }
+        EntryKind::Other => {
+            return Err(invalid_archive(
+                "device, FIFO, socket or hard link entries are not supported",
+            ));
+        }
+        EntryKind::File | EntryKind::Symlink => (),
+    }
+    if let Some(p) = outpath.parent() {
+        fs::create_dir_all(p)?;
+    }
+    // never write through a symlink extracted before
+    if fs::symlink_metadata(&outpath).is_ok_and(|meta| meta.is_symlink()) {
+        fs::remove_file(&outpath)?;
+    }
+
+    if entry.kind == EntryKind::Symlink {
+        let target = entry.target.as_deref().unwrap_or_default();
+        // checked by `validate` already, the archive could differ on a second pass
+        if !symlink_enclosed(&filepath, target) {
+            return Err(invalid_archive(
+                "symlink pointing outside of the installation directory",
+            ));
         }
+        extracted.files.push(filepath);
+        return create_symlink(dir, &outpath, Path::new(target));
+    }
 
-        self.visit(&mut Extractor(directory.as_ref()))
+    let mut outfile = HashWriter {
+        inner: fs::File::create(&outpath)?,
+        hasher: Sha256::new(),
+        crc: crc32fast::Hasher::new(),
+    };
+    extracted.files.push(filepath.clone());
+    // the sizes declared by the archive may lie, what is actually written is checked as well
+    let allowed = allowed_size(limits, extracted.total, entry.compressed_size, archive_size);
+    let written = io::copy(&mut content.take(allowed + 1), &mut outfile)?;
+    check_size(
+        limits,
+        &filepath,
+        extracted.total,
+        written,
+        entry.compressed_size,
+        archive_size,
+    )?;
+    extracted.total += written;
+    // build systems compare the mtimes of headers and libraries
+    if let Some(mtime) = entry.mtime {
+        outfile.inner.set_modified(mtime)?;
     }
+    extracted.expected.push(Expected {
+        path: filepath.clone(),
+        size: entry.size,
+        // formats without checksums are checked against what has been extracted
+        crc32: entry
+            .crc32
+            .unwrap_or_else(|| outfile.crc.clone().finalize()),
+    });
+    extracted
+        .hashes
+        .insert(filepath.clone(), format!("{:x}", outfile.hasher.finalize()));
 
-    #[inline]
-    pub fn visit<V: ZipStreamVisitor>(self, visitor: &mut V) -> ZipResult<()> {
-        self.reader.visit(visitor)
+    let mode = match entry.mode {
+        Some(mode) => mode,
+        // archives made on other systems carry no permissions
+        None if filepath.starts_with("bin") => 0o755,
+        None => 0o644,
+    };
+    apply_mode(&outpath, mode, keep_special_mode_bits)
+}
+
+/// Sets the permissions of `mode` on `path`, without the setuid, setgid and sticky bits unless `keep_special_bits`
+#[allow(unused)]
+fn apply_mode(path: &Path, mode: u32, keep_special_bits: bool) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = if keep_special_bits {
+            mode & 0o7777
+        } else {
+            mode & 0o777
+        };
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+/// What has been extracted so far
+#[derive(Default)]
+struct Extracted {
+    backups: Vec<(PathBuf, PathBuf)>,
+    dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+    hashes: BTreeMap<PathBuf, String>,
+    expected: Vec<Expected>,
+    total: u64,
+}
+
+/// What a regular file is expected to be on disk once extracted
+struct Expected {
+    path: PathBuf,
+    size: u64,
+    crc32: u32,
+}
+
+/// Reads back every extracted file, failing on the first one whose size or CRC-32 does not match the archive.
+fn verify(dir: &Path, expected: &[Expected]) -> io::Result<()> {
+    for expected in expected {
+        let mut crc = crc32fast::Hasher::new();
+        let size = io::copy(
+            &mut fs::File::open(dir.join(&expected.path))?,
+            &mut CrcWriter(&mut crc),
+        )?;
+        let crc32 = crc.finalize();
+        if size != expected.size || crc32 != expected.crc32 {
+            return Err(invalid_archive(&format!(
+                "{} is {size} bytes with CRC-32 {crc32:08x}, expected {} bytes with CRC-32 {:08x}",
+                expected.path.display(),
+                expected.size,
+                expected.crc32
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Creates the symlink `link` to `target`, checked by [`symlink_enclosed`] to stay inside `dir`.
+#[allow(unused)]
+fn create_symlink(dir: &Path, link: &Path, target: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, link)?;
+    #[cfg(not(unix))]
+    {
+        // without the privilege to create symlinks, the target is copied over
+        fs::copy(link.parent().unwrap_or(dir).join(target), link)?;
+    }
+    Ok(())
+}
+
+/// Fails when a directory `path` goes through, relative to `root`, is a symlink, whose target may be anywhere.
+fn check_parents(root: &Path, path: &Path) -> io::Result<()> {
+    for parent in path.ancestors().skip(1) {
+        if parent.as_os_str().is_empty() {
+            break;
+        }
+        if fs::symlink_metadata(root.join(parent)).is_ok_and(|meta| meta.is_symlink()) {
+            return Err(invalid_archive(&format!(
+                "{} goes through the symlink {}",
+                path.display(),
+                parent.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// How many bytes of an entry can be written, `total` bytes having been written before, without exceeding a limit
+fn allowed_size(
+    limits: &ExtractLimits,
+    total: u64,
+    compressed: Option<u64>,
+    archive_size: u64,
+) -> u64 {
+    let ratio = |compressed: u64| {
+        compressed
+            .saturating_mul(limits.max_ratio)
+            .max(RATIO_MIN_BYTES)
+    };
+    let allowed = limits
+        .max_file_bytes
+        .min(limits.max_total_bytes.saturating_sub(total));
+    match compressed {
+        Some(0) => allowed,
+        Some(compressed) => allowed.min(ratio(compressed)),
+        None => allowed.min(ratio(archive_size).saturating_sub(total)),
+    }
+}
+
+/// Checks an entry of `size` bytes, `total` bytes having been written before.
+///
+/// The ratio is checked per entry when its compressed size is known, and for the whole archive otherwise.
+fn check_size(
+    limits: &ExtractLimits,
+    path: &Path,
+    total: u64,
+    size: u64,
+    compressed: Option<u64>,
+    archive_size: u64,
+) -> io::Result<()> {
+    if size > limits.max_file_bytes {
+        return Err(limit_exceeded(
+            path,
+            "max_file_bytes",
+            format_args!("over {}", FmtBytes(limits.max_file_bytes)),
+        ));
+    }
+    if total + size > limits.max_total_bytes {
+        return Err(limit_exceeded(
+            path,
+            "max_total_bytes",
+            format_args!("over {}", FmtBytes(limits.max_total_bytes)),
+        ));
+    }
+    let (size, compressed) = match compressed {
+        Some(compressed) => (size, compressed),
+        None => (total + size, archive_size),
+    };
+    if size > RATIO_MIN_BYTES
+        && compressed > 0
+        && size > compressed.saturating_mul(limits.max_ratio)
+    {
+        return Err(limit_exceeded(
+            path,
+            "max_ratio",
+            format_args!(
+                "{} from {} compressed, over {}x",
+                FmtBytes(size),
+                FmtBytes(compressed),
+                limits.max_ratio
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Writes to `inner` while hashing what goes through
+struct HashWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    crc: crc32fast::Hasher,
+}
+
+impl<W: io::Write> io::Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.crc.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct CrcWriter<'a>(&'a mut crc32fast::Hasher);
+
+impl io::Write for CrcWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads an archive from the registry within the limit of its [`Download`], telling the observer how much of it
+/// has been read
+struct DownloadReader<'a> {
+    inner: Box<dyn io::Read + Send + Sync>,
+    download: Download,
+    package: &'a Package,
+    observer: &'a dyn Observer,
+}
+
+impl io::Read for DownloadReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.download.add(n)?;
+        self.observer.download_progress(
+            self.package,
+            self.download.downloaded,
+            self.download.total,
+        );
+        Ok(n)
     }
 }
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::progress::FmtBytes;
+
+/// What an install, update or uninstall would do, computed without changing anything on disk.
+#[derive(Debug, Serialize)]
+pub struct Plan {
+    pub dir: PathBuf,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Action {
+    Install {
+        name: String,
+        branch: String,
+        arch: Option<String>,
+        /// The currently installed version, if any
+        from: Option<String>,
+        version: String,
+        url: String,
+        size: u64,
+        /// The files written, relative to the installation directory
+        files: Vec<PathBuf>,
+    },
+    Uninstall {
+        name: String,
+        branch: String,
+        version: String,
+    },
+    /// A file or directory, relative to the installation directory
+    Remove { path: PathBuf },
+    /// A file added or modified by the user, either kept in place or backed up as `backup` before being overwritten
+    Preserve {
+        path: PathBuf,
+        backup: Option<PathBuf>,
+    },
+    Skip {
+        name: String,
+        branch: String,
+        reason: String,
+    },
+}
+
+impl Plan {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            actions: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "dry run, nothing changed in {}", self.dir.display())?;
+        if self.actions.is_empty() {
+            writeln!(f, "nothing to do")?;
+        }
+        for action in &self.actions {
+            match action {
+                Action::Install {
+                    name,
+                    branch,
+                    arch,
+                    from,
+                    version,
+                    url,
+                    size,
+                    files,
+                } => {
+                    write!(f, "install {name}@{branch} {version}")?;
+                    if let Some(arch) = arch {
+                        write!(f, " ({arch})")?;
+                    }
+                    match from {
+                        Some(from) => writeln!(f, ", replacing {from}")?,
+                        None => writeln!(f)?,
+                    }
+                    writeln!(f, "  download {url} ({})", FmtBytes(*size))?;
+                    for file in files {
+                        writeln!(f, "  write    {}", file.display())?;
+                    }
+                }
+                Action::Uninstall {
+                    name,
+                    branch,
+                    version,
+                } => writeln!(f, "uninstall {name}@{branch} {version}")?,
+                Action::Remove { path } => writeln!(f, "remove {}", path.display())?,
+                Action::Preserve { path, backup } => match backup {
+                    Some(backup) => {
+                        writeln!(f, "backup {} as {}", path.display(), backup.display())?
+                    }
+                    None => writeln!(f, "keep {}, modified", path.display())?,
+                },
+                Action::Skip {
+                    name,
+                    branch,
+                    reason,
+                } => writeln!(f, "skip {name}@{branch}: {reason}")?,
+            }
+        }
+        Ok(())
+    }
+}
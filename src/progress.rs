@@ -0,0 +1,157 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Reports download and extraction progress on the current stderr line, after the status it was started with.
+///
+/// A disabled `Progress` (quiet mode, or stderr not being a TTY) is a no-op.
+#[derive(Debug, Clone)]
+pub struct Progress(Option<Arc<Mutex<State>>>);
+
+#[derive(Debug)]
+struct State {
+    /// Redrawn before the progress, every draw starting over from the beginning of the line
+    status: String,
+    total: Option<u64>,
+    bytes: u64,
+    files: usize,
+    files_total: Option<usize>,
+    started: Instant,
+    drawn_at: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(enabled: bool, status: String) -> Self {
+        if enabled && io::stderr().is_terminal() {
+            Self(Some(Arc::new(Mutex::new(State {
+                status,
+                total: None,
+                bytes: 0,
+                files: 0,
+                files_total: None,
+                started: Instant::now(),
+                drawn_at: None,
+            }))))
+        } else {
+            Self(None)
+        }
+    }
+
+    pub fn set_total(&self, total: Option<u64>) {
+        self.update(|state| state.total = total);
+    }
+
+    pub fn set_bytes(&self, bytes: u64) {
+        self.update(|state| state.bytes = bytes);
+    }
+
+    pub fn set_files_total(&self, total: Option<usize>) {
+        self.update(|state| state.files_total = total);
+    }
+
+    pub fn set_files(&self, files: usize) {
+        self.update(|state| state.files = files);
+    }
+
+    /// Clears what has been drawn so far, leaving the line as the status it was started with.
+    pub fn finish(&self) {
+        if let Some(state) = &self.0 {
+            let state = state.lock().unwrap_or_else(|err| err.into_inner());
+            if state.drawn_at.is_some() {
+                let mut stderr = io::stderr().lock();
+                write!(stderr, "\r\x1b[K{}", state.status).ok();
+                stderr.flush().ok();
+            }
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        if let Some(state) = &self.0 {
+            let mut state = state.lock().unwrap_or_else(|err| err.into_inner());
+            f(&mut state);
+            state.draw();
+        }
+    }
+}
+
+impl State {
+    fn draw(&mut self) {
+        let now = Instant::now();
+        if self
+            .drawn_at
+            .is_some_and(|drawn_at| now - drawn_at < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        self.drawn_at = Some(now);
+
+        let elapsed = (now - self.started).as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            self.bytes as f64 / elapsed
+        } else {
+            0.0
+        };
+
+        let mut line = match self.total {
+            Some(total) if total > 0 => {
+                let percent = self.bytes.min(total) * 100 / total;
+                let eta = if rate > 0.0 {
+                    FmtDuration(total.saturating_sub(self.bytes) as f64 / rate).to_string()
+                } else {
+                    "--".to_string()
+                };
+                format!(
+                    "{} / {} ({percent}%) {}/s ETA {eta}",
+                    FmtBytes(self.bytes),
+                    FmtBytes(total),
+                    FmtBytes(rate as u64),
+                )
+            }
+            _ => format!("{} {}/s", FmtBytes(self.bytes), FmtBytes(rate as u64)),
+        };
+        match self.files_total {
+            Some(total) => line.push_str(&format!(", {} / {total} files", self.files)),
+            None if self.files > 0 => line.push_str(&format!(", {} files", self.files)),
+            None => (),
+        }
+
+        let mut stderr = io::stderr().lock();
+        write!(stderr, "\r\x1b[K{}{line}", self.status).ok();
+        stderr.flush().ok();
+    }
+}
+
+pub struct FmtBytes(pub u64);
+
+impl std::fmt::Display for FmtBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut value = self.0 as f64 / 1024.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        write!(f, "{value:.1} {}", UNITS[unit])
+    }
+}
+
+struct FmtDuration(f64);
+
+impl std::fmt::Display for FmtDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.0.round() as u64;
+        if secs >= 3600 {
+            write!(f, "{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+        } else if secs >= 60 {
+            write!(f, "{}m{:02}s", secs / 60, secs % 60)
+        } else {
+            write!(f, "{secs}s")
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::package::{Installed, Package};
+
+const RECEIPT_FILE: &str = "receipt.json";
+
+/// What gcm installed in a directory, written by `gcm install` next to `bin/`, `lib/`, etc.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Receipt {
+    #[serde(default)]
+    pub dir: PathBuf,
+    pub packages: Vec<InstalledPackage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct InstalledPackage {
+    pub name: String,
+    pub branch: String,
+    pub version: String,
+    pub arch: Option<String>,
+    /// Restricts the versions `gcm update` can move this package to, eg. `=7.0.1-dev` for an explicit version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<VersionReq>,
+    pub installed_at: DateTime<Local>,
+    /// The files extracted by this package, relative to the installation directory
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+    /// The sha256 of the regular `files` as extracted, telling which ones have been modified since
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hashes: BTreeMap<PathBuf, String>,
+}
+
+impl InstalledPackage {
+    pub fn package(&self) -> Package {
+        Package::new(&self.name, self.arch.clone(), &self.branch)
+    }
+
+    /// Whether `gcm update` may move this package to `version`.
+    ///
+    /// GreyCat versions always carry their branch as pre-release (eg. `7.0.1-stable`), which a range like `^7.0`
+    /// would never match, so the pin is also checked against the plain `major.minor.patch`.
+    pub fn allows(&self, version: &Version) -> bool {
+        match &self.pin {
+            Some(pin) => {
+                pin.matches(version)
+                    || pin.matches(&Version::new(version.major, version.minor, version.patch))
+            }
+            None => true,
+        }
+    }
+
+    /// The files of this package in `dir` whose content changed since they were extracted.
+    ///
+    /// Packages recorded before hashes were kept have no modified files.
+    pub(crate) fn modified_files(&self, dir: &Path) -> Vec<PathBuf> {
+        self.hashes
+            .iter()
+            .filter(|(file, hash)| {
+                let path = dir.join(file);
+                fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_file())
+                    && sha256_file(&path).is_ok_and(|actual| actual != **hash)
+            })
+            .map(|(file, _)| file.clone())
+            .collect()
+    }
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Removes `files` (relative to `dir`), pruning the directories they leave empty.
+pub fn remove_files(dir: &Path, files: &[PathBuf]) -> Result<()> {
+    for file in files {
+        let path = dir.join(file);
+        match fs::remove_file(&path) {
+            Ok(()) => prune_empty_parents(dir, &path),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => {
+                return Err(err).with_context(|| format!("removing {}", path.display()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn prune_empty_parents(dir: &Path, path: &Path) {
+    let mut parent = path.parent();
+    while let Some(p) = parent {
+        // `remove_dir` only succeeds on empty directories
+        if p == dir || !p.starts_with(dir) || fs::remove_dir(p).is_err() {
+            break;
+        }
+        parent = p.parent();
+    }
+}
+
+impl Receipt {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            packages: Vec::new(),
+        }
+    }
+
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(RECEIPT_FILE)
+    }
+
+    /// Returns `None` when nothing has been installed by gcm in `dir` yet.
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = Self::path(dir);
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("reading {}", path.display()));
+            }
+        };
+        let receipt = serde_json::from_slice(&content)
+            .with_context(|| format!("invalid receipt {}", path.display()))?;
+        Ok(Some(receipt))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = Self::path(dir);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("writing {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("writing {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&InstalledPackage> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// Whether `file`, relative to `dir`, has been added or modified by the user rather than extracted as is.
+    pub fn is_user_file(&self, dir: &Path, file: &Path) -> bool {
+        let mut owners = self
+            .packages
+            .iter()
+            .filter(|p| p.files.iter().any(|f| f == file))
+            .peekable();
+        if owners.peek().is_none() {
+            return true;
+        }
+        match owners.find_map(|p| p.hashes.get(file)) {
+            Some(hash) => sha256_file(&dir.join(file)).is_ok_and(|actual| actual != *hash),
+            None => false,
+        }
+    }
+
+    /// The files of the installed `name` that are neither part of `files` nor owned by another package.
+    pub fn exclusive_files(&self, name: &str, files: &[PathBuf]) -> Vec<PathBuf> {
+        let Some(installed) = self.get(name) else {
+            return Vec::new();
+        };
+        let keep: HashSet<&PathBuf> = self
+            .packages
+            .iter()
+            .filter(|p| p.name != name)
+            .flat_map(|p| &p.files)
+            .chain(files)
+            .collect();
+        installed
+            .files
+            .iter()
+            .filter(|file| !keep.contains(file))
+            .cloned()
+            .collect()
+    }
+
+    /// Records `package` as installed, replacing any previous entry of the same name.
+    pub fn record(&mut self, package: &Package, installed: Installed, pin: Option<VersionReq>) {
+        let entry = InstalledPackage {
+            name: package.name.clone(),
+            branch: package.branch.clone(),
+            version: installed.version.version,
+            arch: package.arch.clone(),
+            pin,
+            installed_at: Local::now(),
+            files: installed.files,
+            hashes: installed.hashes,
+        };
+        match self.packages.iter_mut().find(|p| p.name == package.name) {
+            Some(previous) => *previous = entry,
+            None => self.packages.push(entry),
+        }
+    }
+}
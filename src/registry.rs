@@ -1,90 +1,114 @@
 use std::str::FromStr;
 
-use anyhow::{Context, Result};
+use anyhow::Context;
 use chrono::{DateTime, Local};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 
+use crate::Result;
+use crate::archive::Format;
+use crate::package::{self, Package};
+
+const DEFAULT_URL: &str = "https://get.greycat.io/files";
+
+/// Overrides the registry url, eg. to use a mirror
+const REGISTRY_VAR: &str = "GCM_REGISTRY";
+
+#[derive(Debug, Clone)]
 pub struct Registry {
     url: String,
 }
 
 impl Default for Registry {
+    /// The registry at `$GCM_REGISTRY`, or the official one at `https://get.greycat.io/files`
     fn default() -> Self {
-        Self {
-            url: "https://get.greycat.io/files".to_string(),
+        match std::env::var(REGISTRY_VAR) {
+            Ok(url) if !url.is_empty() => Self::new(url),
+            _ => Self::new(DEFAULT_URL),
         }
     }
 }
 
 impl Registry {
+    pub fn new(url: impl Into<String>) -> Self {
+        let mut url = url.into();
+        while url.ends_with('/') {
+            url.pop();
+        }
+        Self { url }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The url of a file of the registry, eg. `core/stable/latest`
+    pub fn file_url(&self, path: &str) -> String {
+        format!("{}/{path}", self.url)
+    }
+
+    /// The latest version of `package`, as published in `<name>/<branch>/latest`
+    pub fn latest(&self, package: &Package) -> Result<package::Version> {
+        let url = self.file_url(&format!("{}/{}/latest", package.name, package.branch));
+        let latest: String = ureq::get(&url).call()?.into_string()?;
+        package::Version::try_from(latest)
+    }
+
     pub fn list_package_versions(
         &self,
         name: &str,
         branch: &str,
         limit: Option<usize>,
     ) -> Result<Vec<PackageVersion>> {
-        let entries: Vec<File> = ureq::get(&format!("{}/{name}/{branch}/", self.url))
+        let entries: Vec<File> = ureq::get(&self.file_url(&format!("{name}/{branch}/")))
             .call()
             .with_context(|| format!("no version found for \"{name}/{branch}\""))?
             .into_json()?;
 
         let mut versions = Vec::default();
-
-        for file in entries {
-            if file.path.ends_with('/') {
-                let targets: Vec<File> = ureq::get(&format!("{}/{}", self.url, file.path))
-                    .call()
-                    .with_context(|| format!("no version found for \"{name}/{branch}\""))?
-                    .into_json()?;
-                for file in targets {
-                    if file.path.ends_with('/') {
-                        add_entries(file, &mut versions)?;
-                    } else if file.path.ends_with(".zip") {
-                        let (_, version) =
-                            file.path[..file.path.len() - 4].rsplit_once('/').unwrap();
-                        if let Ok(version) = Version::from_str(version) {
-                            versions.push(PackageVersion {
-                                last_modified: file.last_modification,
-                                version,
-                            });
-                        }
-                    }
-                }
+        for file in entries.iter().filter(|file| file.path.ends_with('/')) {
+            let targets: Vec<File> = ureq::get(&self.file_url(&file.path))
+                .call()
+                .with_context(|| format!("no version found for \"{name}/{branch}\""))?
+                .into_json()?;
+            // the archives are either right there, or in a sub-directory per architecture
+            for dir in targets.iter().filter(|file| file.path.ends_with('/')) {
+                let files: Vec<File> = ureq::get(&self.file_url(&dir.path)).call()?.into_json()?;
+                versions.extend(archive_versions(&files));
             }
+            versions.extend(archive_versions(&targets));
         }
 
-        versions.sort();
-        versions.dedup();
-        if let Some(limit) = limit {
-            let len = versions.len();
-            if len > limit {
-                versions = versions.drain(versions.len() - limit..).collect();
-            }
-        }
+        Ok(latest_versions(versions, limit))
+    }
 
-        Ok(versions)
+    /// Lists the content of a registry directory, eg. `core/stable/7.0/`
+    pub fn list_files(&self, path: &str) -> Result<Vec<File>> {
+        let files = ureq::get(&self.file_url(path))
+            .call()
+            .with_context(|| format!("unable to list \"{path}\""))?
+            .into_json()?;
+        Ok(files)
     }
 
     pub fn list_package_branches(&self, name: &str) -> Result<Vec<File>> {
-        let branches = ureq::get(&format!("{}/{name}/", self.url))
+        let branches = ureq::get(&self.file_url(&format!("{name}/")))
             .call()?
             .into_json()?;
         Ok(branches)
     }
 
     pub fn list_packages(&self) -> Result<Vec<File>> {
-        let files: Vec<File> = ureq::get(&format!("{}/", self.url)).call()?.into_json()?;
+        let files: Vec<File> = ureq::get(&self.file_url("")).call()?.into_json()?;
 
         let mut packages = Vec::default();
         for file in files {
-            match file.path.as_str() {
-                "core/" | "lang/" => packages.push(file),
-                "deps/" => (), // ignore
-                _ => {
-                    let files: Vec<File> = ureq::get(&format!("{}/{}", self.url, file.path))
-                        .call()?
-                        .into_json()?;
+            match Listing::of(&file) {
+                Listing::Package => packages.push(file),
+                Listing::Ignored => (),
+                Listing::Group => {
+                    let files: Vec<File> =
+                        ureq::get(&self.file_url(&file.path)).call()?.into_json()?;
                     packages.extend(files);
                 }
             }
@@ -97,15 +121,21 @@ impl Registry {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct File {
     pub last_modification: DateTime<Local>,
     pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct PackageVersion {
-    last_modified: DateTime<Local>,
-    version: Version,
+    pub last_modified: DateTime<Local>,
+    pub version: Version,
 }
 
 impl std::fmt::Display for PackageVersion {
@@ -139,22 +169,48 @@ impl std::cmp::PartialOrd for PackageVersion {
     }
 }
 
-fn add_entries(file: File, versions: &mut Vec<PackageVersion>) -> anyhow::Result<()> {
-    let entries: Vec<File> = ureq::get(&format!("https://get.greycat.io/files/{}", file.path))
-        .call()?
-        .into_json()?;
-
-    for file in entries {
-        if file.path.ends_with(".zip") {
-            let (_, version) = file.path[..file.path.len() - 4].rsplit_once('/').unwrap();
-            if let Ok(version) = Version::from_str(version) {
-                versions.push(PackageVersion {
-                    last_modified: file.last_modification,
-                    version,
-                });
-            }
+/// What a top-level entry of the registry holds
+pub(crate) enum Listing {
+    Package,
+    /// A directory of packages, eg. `sdk/`
+    Group,
+    Ignored,
+}
+
+impl Listing {
+    pub(crate) fn of(file: &File) -> Self {
+        match file.path.as_str() {
+            "core/" | "lang/" => Self::Package,
+            "deps/" => Self::Ignored,
+            _ => Self::Group,
         }
     }
+}
+
+/// The versions of the archives in `files`, the content of a registry directory
+pub(crate) fn archive_versions(files: &[File]) -> impl Iterator<Item = PackageVersion> + '_ {
+    files.iter().filter_map(|file| {
+        let (path, _) = Format::split(&file.path)?;
+        let (_, version) = path.rsplit_once('/')?;
+        Some(PackageVersion {
+            last_modified: file.last_modification,
+            version: Version::from_str(version).ok()?,
+        })
+    })
+}
 
-    Ok(())
+/// Sorts `versions`, the same version being published in several formats, and keeps the `limit` latest ones.
+pub(crate) fn latest_versions(
+    mut versions: Vec<PackageVersion>,
+    limit: Option<usize>,
+) -> Vec<PackageVersion> {
+    versions.sort();
+    versions.dedup();
+    if let Some(limit) = limit {
+        let len = versions.len();
+        if len > limit {
+            versions = versions.drain(versions.len() - limit..).collect();
+        }
+    }
+    versions
 }
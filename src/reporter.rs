@@ -0,0 +1,221 @@
+use std::fmt::Display;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+use crate::observer::{Observer, Skipped};
+use crate::package::Package;
+use crate::progress::Progress;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Every human-readable message gcm writes to stderr goes through the `Reporter`.
+///
+/// Command results (eg. the output of `gcm list`) are still written to stdout directly.
+#[derive(Debug)]
+pub struct Reporter {
+    color: ColorChoice,
+    quiet: bool,
+    verbose: u8,
+    /// The status line started and not completed yet, drawn again along with the progress
+    status: Mutex<String>,
+    /// The progress of the package being downloaded or installed, drawn from the observed events
+    progress: Mutex<Option<Progress>>,
+}
+
+impl Reporter {
+    pub fn new(color: ColorMode, quiet: bool, verbose: u8) -> Self {
+        let color = match color {
+            ColorMode::Always => ColorChoice::AlwaysAnsi,
+            ColorMode::Never => ColorChoice::Never,
+            ColorMode::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+                if no_color || !io::stderr().is_terminal() {
+                    ColorChoice::Never
+                } else {
+                    ColorChoice::Auto
+                }
+            }
+        };
+
+        Self {
+            color,
+            quiet,
+            verbose,
+            status: Mutex::new(String::new()),
+            progress: Mutex::new(None),
+        }
+    }
+
+    /// Starts a status line, meant to be completed by [`Reporter::done`].
+    pub fn status(&self, msg: impl Display) {
+        if !self.quiet {
+            let msg = msg.to_string();
+            eprint!("{msg}");
+            *self.status.lock().unwrap_or_else(|err| err.into_inner()) = msg;
+        }
+    }
+
+    /// Completes a status line started with [`Reporter::status`].
+    pub fn done(&self, color: Color, msg: impl Display) {
+        if !self.quiet {
+            self.status
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .clear();
+            self.colored(color, &msg).ok();
+        }
+    }
+
+    pub fn info(&self, msg: impl Display) {
+        if !self.quiet {
+            eprintln!("{msg}");
+        }
+    }
+
+    /// Only displayed when `-v` has been given at least `level` times.
+    pub fn verbose(&self, level: u8, msg: impl Display) {
+        if !self.quiet && self.verbose >= level {
+            eprintln!("{msg}");
+        }
+    }
+
+    /// Warnings are displayed even in quiet mode.
+    pub fn warn(&self, msg: impl Display) {
+        self.labelled(Color::Yellow, "warning", &msg).ok();
+    }
+
+    pub fn note(&self, msg: impl Display) {
+        if !self.quiet {
+            self.labelled(Color::Cyan, "note", &msg).ok();
+        }
+    }
+
+    /// Writes `msg` to stdout after a colored `label`, for command results like the checks of `gcm doctor`.
+    pub fn check(&self, color: Color, label: &str, msg: impl Display) {
+        let choice = if io::stdout().is_terminal() {
+            self.color
+        } else {
+            ColorChoice::Never
+        };
+        let mut stdout = StandardStream::stdout(choice);
+        stdout.set_color(ColorSpec::new().set_fg(Some(color))).ok();
+        write!(&mut stdout, "{label:>4}").ok();
+        stdout.reset().ok();
+        writeln!(&mut stdout, "  {msg}").ok();
+    }
+
+    /// Clears the progress drawn from the observed events, before the status line gets completed.
+    pub fn finish_progress(&self) {
+        let progress = self
+            .progress
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take();
+        if let Some(progress) = progress {
+            progress.finish();
+        }
+    }
+
+    fn labelled(&self, color: Color, label: &str, msg: &dyn Display) -> io::Result<()> {
+        let mut stderr = StandardStream::stderr(self.color);
+        stderr.set_color(ColorSpec::new().set_fg(Some(color)))?;
+        write!(&mut stderr, "{label}")?;
+        stderr.reset()?;
+        writeln!(&mut stderr, ": {msg}")
+    }
+
+    fn colored(&self, color: Color, msg: &dyn Display) -> io::Result<()> {
+        let mut stderr = StandardStream::stderr(self.color);
+        stderr.set_color(ColorSpec::new().set_fg(Some(color)))?;
+        writeln!(&mut stderr, "{msg}")?;
+        stderr.reset()
+    }
+
+    fn with_progress(&self, f: impl FnOnce(&Progress)) {
+        let mut progress = self.progress.lock().unwrap_or_else(|err| err.into_inner());
+        f(progress.get_or_insert_with(|| {
+            let status = self.status.lock().unwrap_or_else(|err| err.into_inner());
+            Progress::new(!self.quiet, status.clone())
+        }));
+    }
+}
+
+/// The CLI output of install, update and uninstall
+impl Observer for Reporter {
+    fn resolving(&self, package: &Package) {
+        self.verbose(1, format_args!("resolving the latest version of {package}"));
+    }
+
+    fn planning(&self, package: &Package) {
+        self.status(format_args!("resolving  {:20} ", package.to_string()));
+    }
+
+    fn planned(&self, _: &Package, version: &str) {
+        self.finish_progress();
+        self.done(Color::Green, version);
+    }
+
+    fn installing(&self, package: &Package) {
+        self.status(format_args!("installing {:20} ", package.to_string()));
+    }
+
+    fn download_progress(&self, _: &Package, downloaded: u64, total: Option<u64>) {
+        self.with_progress(|progress| {
+            progress.set_total(total);
+            progress.set_bytes(downloaded);
+        });
+    }
+
+    fn file_extracted(&self, _: &Package, _: &Path, count: usize, total: usize) {
+        self.with_progress(|progress| {
+            progress.set_files_total(Some(total));
+            progress.set_files(count);
+        });
+    }
+
+    fn installed(&self, _: &Package, version: &str) {
+        self.finish_progress();
+        self.done(Color::Green, version);
+    }
+
+    fn skipped(&self, _: &Package, reason: Skipped<'_>) {
+        self.finish_progress();
+        match reason {
+            Skipped::NotFound => self.done(Color::Yellow, "not found"),
+            Skipped::Failed(err) => self.done(Color::Yellow, format_args!("skipped: {err}")),
+        }
+    }
+
+    fn uninstalling(&self, package: &Package, version: &str) {
+        self.info(format_args!("uninstalling {package} {version}"));
+    }
+
+    fn kept(&self, path: &Path, backup: Option<&Path>) {
+        match backup {
+            Some(backup) => self.note(format_args!(
+                "{} was replaced, your version is kept as {}",
+                path.display(),
+                backup.display()
+            )),
+            None => self.note(format_args!("{} was modified, kept", path.display())),
+        }
+    }
+
+    fn message(&self, msg: &dyn Display) {
+        self.info(msg);
+    }
+
+    fn warning(&self, msg: &dyn Display) {
+        self.warn(msg);
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::toolchain::{exec, installations};
+
+const SHIMS_DIR: &str = "shims";
+
+pub fn shims_dir(home: &Path) -> PathBuf {
+    home.join(SHIMS_DIR)
+}
+
+/// The name of the binary gcm stands for, when it has been invoked through one of its shims.
+///
+/// Shims are copies of the gcm executable named after a GreyCat binary, eg. `$GREYCAT_HOME/shims/greycat`.
+pub fn invoked_as() -> Option<(String, PathBuf)> {
+    let exe = std::env::current_exe().ok()?;
+    let dir = exe.parent()?;
+    if dir.file_name()? != SHIMS_DIR {
+        return None;
+    }
+    let name = exe.file_name()?.to_str()?;
+    let name = name
+        .strip_suffix(std::env::consts::EXE_SUFFIX)
+        .unwrap_or(name);
+    if name == "gcm" {
+        return None;
+    }
+    Some((name.to_string(), dir.parent()?.to_path_buf()))
+}
+
+/// Runs the binary `name` of the installation `dir`, forwarding the arguments the shim was given.
+pub fn run(name: &str, dir: &Path) -> Result<()> {
+    let bin = dir.join("bin");
+    if !bin
+        .join(format!("{name}{}", std::env::consts::EXE_SUFFIX))
+        .is_file()
+    {
+        // falling back to PATH would end up in this shim again
+        bail!("'{name}' is not installed in {}", dir.display());
+    }
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    exec(dir, name, &args)
+}
+
+/// Creates a shim for every binary in the `bin/` of `home` and of its toolchains, and removes the others.
+pub fn sync(home: &Path) -> Result<()> {
+    let mut names = BTreeSet::new();
+    for dir in installations(home)? {
+        let Ok(entries) = fs::read_dir(dir.join("bin")) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_file()
+                && let Some(name) = path.file_name().and_then(|name| name.to_str())
+            {
+                names.insert(name.to_string());
+            }
+        }
+    }
+
+    let shims = shims_dir(home);
+    if let Ok(entries) = fs::read_dir(&shims) {
+        for entry in entries {
+            let path = entry?.path();
+            let stale = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_none_or(|name| !names.contains(name));
+            if stale {
+                fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+            }
+        }
+    }
+    if names.is_empty() {
+        fs::remove_dir(&shims).ok();
+        return Ok(());
+    }
+
+    fs::create_dir_all(&shims).with_context(|| format!("creating {}", shims.display()))?;
+    let exe = std::env::current_exe().context("unable to locate the gcm executable")?;
+    for name in names {
+        let shim = shims.join(name);
+        if is_same_executable(&exe, &shim) {
+            continue;
+        }
+        // replaced through a temporary file, the shim may be running
+        let tmp = shim.with_extension("gcm-tmp");
+        fs::remove_file(&tmp).ok();
+        if fs::hard_link(&exe, &tmp).is_err() {
+            fs::copy(&exe, &tmp).with_context(|| format!("writing {}", tmp.display()))?;
+        }
+        fs::rename(&tmp, &shim).with_context(|| format!("writing {}", shim.display()))?;
+    }
+    Ok(())
+}
+
+/// Whether `shim` is already a link to, or an up-to-date copy of `exe`
+fn is_same_executable(exe: &Path, shim: &Path) -> bool {
+    let (Ok(exe), Ok(shim)) = (fs::metadata(exe), fs::metadata(shim)) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if exe.dev() == shim.dev() && exe.ino() == shim.ino() {
+            return true;
+        }
+    }
+    exe.len() == shim.len() && matches!((exe.modified(), shim.modified()), (Ok(a), Ok(b)) if a <= b)
+}
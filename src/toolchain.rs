@@ -0,0 +1,294 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use semver::{Version, VersionReq};
+
+use crate::config::Config;
+use crate::install::{Target, default_targets, install_targets};
+use crate::observer::Observer;
+use crate::receipt::{InstalledPackage, Receipt};
+use crate::registry::{PackageVersion, Registry};
+
+/// The additional installations live next to the default one, in `$GREYCAT_HOME/toolchains/<name>/`
+const TOOLCHAINS_DIR: &str = "toolchains";
+
+const TOOLCHAIN_VAR: &str = "GREYCAT_TOOLCHAIN";
+
+/// Selects the toolchain of a project, looked up from the current directory up to the root
+const TOOLCHAIN_FILE: &str = ".greycat-toolchain";
+
+/// A GreyCat version to run, as given to `gcm run +<toolchain>`
+///
+/// - a branch: `dev`, `stable`
+/// - a version or version prefix, optionally followed by its branch: `6.10`, `7.0.1-dev`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Toolchain {
+    Branch(String),
+    Version { req: VersionReq, branch: String },
+}
+
+impl FromStr for Toolchain {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.strip_prefix('+').unwrap_or(s);
+        if s.is_empty() {
+            bail!("empty toolchain, expected eg. 'dev' or '7.0'");
+        }
+        if !s.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(Self::Branch(s.to_string()));
+        }
+        let (version, branch) = s.split_once('-').unwrap_or((s, "stable"));
+        let req = VersionReq::parse(&format!("={version}"))
+            .with_context(|| format!("invalid toolchain '{s}'"))?;
+        Ok(Self::Version {
+            req,
+            branch: branch.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for Toolchain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Branch(branch) => write!(f, "{branch}"),
+            Self::Version { req, branch } => {
+                write!(f, "{}-{branch}", req.to_string().trim_start_matches('='))
+            }
+        }
+    }
+}
+
+impl Toolchain {
+    pub fn branch(&self) -> &str {
+        match self {
+            Self::Branch(branch) | Self::Version { branch, .. } => branch,
+        }
+    }
+
+    /// Whether the installed `core` satisfies this toolchain
+    pub fn matches(&self, core: &InstalledPackage) -> bool {
+        if core.branch != self.branch() {
+            return false;
+        }
+        match self {
+            Self::Branch(_) => true,
+            Self::Version { req, .. } => Version::parse(&core.version)
+                .is_ok_and(|v| req.matches(&Version::new(v.major, v.minor, v.patch))),
+        }
+    }
+
+    /// Finds the installation of this toolchain in `home`, installing it in `home/toolchains/` if there is none.
+    pub fn resolve(
+        &self,
+        home: &Path,
+        registry: &Registry,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf> {
+        if let Some(dir) = self.find(home)? {
+            return Ok(dir);
+        }
+
+        let versions = match self {
+            Self::Branch(_) => Vec::new(),
+            Self::Version { branch, .. } => registry.list_package_versions("core", branch, None)?,
+        };
+        let (dir, targets) = self.targets(home, versions)?;
+        observer.message(&format_args!(
+            "toolchain '{self}' is not installed, installing it in {}",
+            dir.display()
+        ));
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        let installed = install_targets(&dir, targets, registry, observer);
+        self.installed(dir, installed)
+    }
+
+    /// Where to install this toolchain in `home` and what, given the `versions` of core on its branch.
+    pub(crate) fn targets(
+        &self,
+        home: &Path,
+        versions: Vec<PackageVersion>,
+    ) -> Result<(PathBuf, Vec<Target>)> {
+        let (dir, version) = match self {
+            Self::Branch(branch) => (toolchains_dir(home).join(branch), None),
+            Self::Version { req, branch } => {
+                let version = versions
+                    .into_iter()
+                    .map(|version| version.version)
+                    .filter(|v| req.matches(&Version::new(v.major, v.minor, v.patch)))
+                    .max()
+                    .with_context(|| format!("no version of core@{branch} matches '{self}'"))?;
+                (
+                    toolchains_dir(home).join(version.to_string()),
+                    Some(version),
+                )
+            }
+        };
+
+        let mut targets = default_targets(None, self.branch());
+        if let (Self::Version { req, .. }, Some(version)) = (self, version) {
+            // lang is released along with core, explorer has its own versions
+            for target in targets
+                .iter_mut()
+                .filter(|target| matches!(target.package.name.as_str(), "core" | "lang"))
+            {
+                target.version = Some(version.clone());
+                target.pin = Some(req.clone());
+            }
+        }
+        Ok((dir, targets))
+    }
+
+    /// Checks the outcome of installing this toolchain in `dir`, removed unless its core got installed.
+    pub(crate) fn installed(&self, dir: PathBuf, installed: Result<Receipt>) -> Result<PathBuf> {
+        let installed = installed.and_then(|receipt| {
+            if receipt.get("core").is_none() {
+                bail!("unable to install core for toolchain '{self}'");
+            }
+            Ok(())
+        });
+        if let Err(err) = installed {
+            // do not leave a half-installed toolchain behind
+            fs::remove_dir_all(&dir).ok();
+            return Err(err);
+        }
+        Ok(dir)
+    }
+
+    /// The installation, among the default one and those of `home/toolchains/`, with the highest matching core.
+    pub fn find(&self, home: &Path) -> Result<Option<PathBuf>> {
+        let mut best: Option<(Version, PathBuf)> = None;
+        for dir in installations(home)? {
+            let Ok(Some(receipt)) = Receipt::load(&dir) else {
+                continue;
+            };
+            let Some(core) = receipt.get("core").filter(|core| self.matches(core)) else {
+                continue;
+            };
+            let Ok(version) = Version::parse(&core.version) else {
+                continue;
+            };
+            if best.as_ref().is_none_or(|(best, _)| version > *best) {
+                best = Some((version, dir));
+            }
+        }
+        Ok(best.map(|(_, dir)| dir))
+    }
+}
+
+pub fn toolchains_dir(home: &Path) -> PathBuf {
+    home.join(TOOLCHAINS_DIR)
+}
+
+/// The default installation `home` followed by every toolchain installed next to it
+pub fn installations(home: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = vec![home.to_path_buf()];
+    let toolchains = toolchains_dir(home);
+    match fs::read_dir(&toolchains) {
+        Ok(entries) => {
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                }
+            }
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => {
+            return Err(err).with_context(|| format!("reading {}", toolchains.display()));
+        }
+    }
+    dirs[1..].sort();
+    Ok(dirs)
+}
+
+/// The directory holding `toolchains/`, for `dir` being either the default installation or one of its toolchains
+pub fn home_of(dir: &Path) -> &Path {
+    match dir.parent() {
+        Some(parent)
+            if parent
+                .file_name()
+                .is_some_and(|name| name == TOOLCHAINS_DIR) =>
+        {
+            parent.parent().unwrap_or(dir)
+        }
+        _ => dir,
+    }
+}
+
+/// The toolchain selected by `$GREYCAT_TOOLCHAIN`, the closest `.greycat-toolchain` file or the `default_toolchain`
+/// of the config, in that order, along with what selected it.
+///
+/// `None` stands for the default installation of `home`.
+pub fn active(home: &Path) -> Result<Option<(Toolchain, String)>> {
+    if let Ok(toolchain) = std::env::var(TOOLCHAIN_VAR)
+        && !toolchain.is_empty()
+    {
+        return Ok(Some((toolchain.parse()?, format!("${TOOLCHAIN_VAR}"))));
+    }
+
+    let cwd = std::env::current_dir().context("unable to read the current directory")?;
+    for dir in cwd.ancestors() {
+        let path = dir.join(TOOLCHAIN_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err).with_context(|| format!("reading {}", path.display())),
+        };
+        if let Some(toolchain) = content.lines().map(str::trim).find(|l| !l.is_empty()) {
+            let toolchain = toolchain
+                .parse()
+                .with_context(|| format!("in {}", path.display()))?;
+            return Ok(Some((toolchain, path.display().to_string())));
+        }
+    }
+
+    match Config::load(home)?.default_toolchain {
+        Some(toolchain) => Ok(Some((
+            toolchain.parse()?,
+            Config::path(home).display().to_string(),
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Runs `program` with the GreyCat installed in `dir`, exiting with its status.
+///
+/// `program` is looked up in `dir/bin/` first, then in `PATH`.
+pub fn exec(dir: &Path, program: &str, args: &[String]) -> Result<()> {
+    let bin = dir.join("bin");
+    let local = bin.join(format!("{program}{}", std::env::consts::EXE_SUFFIX));
+    let program = if local.is_file() {
+        local
+    } else {
+        PathBuf::from(program)
+    };
+
+    let mut paths = vec![bin];
+    if let Some(path) = std::env::var_os("PATH") {
+        paths.extend(std::env::split_paths(&path));
+    }
+    let mut cmd = Command::new(&program);
+    cmd.args(args)
+        .env("GREYCAT_HOME", dir)
+        .env("PATH", std::env::join_paths(paths)?);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // only returns on failure
+        let err = cmd.exec();
+        Err(err).with_context(|| format!("unable to run '{}'", program.display()))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let status = cmd
+            .status()
+            .with_context(|| format!("unable to run '{}'", program.display()))?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
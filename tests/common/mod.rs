@@ -0,0 +1,321 @@
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Cursor, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use zip::write::FileOptions;
+
+/// A registry served over HTTP from memory, in the layout of `https://get.greycat.io/files`.
+///
+/// Directory listings are computed from the files published, as the JSON array of `File` the real registry answers.
+pub struct MockRegistry {
+    url: String,
+    files: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MockRegistry {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let files = Arc::new(Mutex::new(BTreeMap::new()));
+        let served = files.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let files = served.clone();
+                std::thread::spawn(move || serve(stream, &files));
+            }
+        });
+        Self { url, files }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Publishes `content` at `path`, eg. `core/stable/latest`
+    pub fn file(&self, path: &str, content: impl Into<Vec<u8>>) {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), content.into());
+    }
+
+    /// Publishes the `archive` of `name@version` at the place gcm looks for it, eg.
+    /// `core/stable/7.0/x64-linux/7.0.1-stable.zip`
+    pub fn publish(
+        &self,
+        name: &str,
+        arch: Option<&str>,
+        version: &str,
+        ext: &str,
+        archive: Vec<u8>,
+    ) {
+        let branch = version
+            .split_once('-')
+            .map_or("stable", |(_, branch)| branch);
+        let mut dir = format!("{name}/{branch}/{}/", major_minor(version));
+        if let Some(arch) = arch {
+            dir.push_str(&format!("{arch}/"));
+        }
+        self.file(&format!("{dir}{version}{ext}"), archive);
+    }
+
+    /// Makes `version` the one `<name>/<branch>/latest` points to
+    pub fn latest(&self, name: &str, version: &str) {
+        let branch = version
+            .split_once('-')
+            .map_or("stable", |(_, branch)| branch);
+        self.file(
+            &format!("{name}/{branch}/latest"),
+            format!("{}/{version}", major_minor(version)),
+        );
+    }
+}
+
+/// The URL of a registry nobody answers at
+pub fn unreachable_url() -> String {
+    // nothing listens on a port once its listener is dropped
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+fn major_minor(version: &str) -> String {
+    let mut parts = version.split('.');
+    format!("{}.{}", parts.next().unwrap(), parts.next().unwrap())
+}
+
+fn serve(mut stream: TcpStream, files: &Mutex<BTreeMap<String, Vec<u8>>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request = String::new();
+    if reader.read_line(&mut request).is_err() {
+        return;
+    }
+    // the headers are not needed, but must be read before answering
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+        line.clear();
+    }
+    let path = request
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or("/")
+        .trim_start_matches('/')
+        .to_string();
+
+    let files = files.lock().unwrap();
+    let (status, content_type, body) = match files.get(&path) {
+        Some(content) => ("200 OK", "application/octet-stream", content.clone()),
+        None => match listing(&files, &path) {
+            Some(listing) => ("200 OK", "application/json", listing.into_bytes()),
+            None => ("404 Not Found", "text/plain", b"not found".to_vec()),
+        },
+    };
+    drop(files);
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).ok();
+    stream.write_all(&body).ok();
+}
+
+/// The direct children of the directory `path`, `None` when nothing is published under it
+fn listing(files: &BTreeMap<String, Vec<u8>>, path: &str) -> Option<String> {
+    if !(path.is_empty() || path.ends_with('/')) {
+        return None;
+    }
+    let mut children: Vec<String> = files
+        .keys()
+        .filter_map(|file| {
+            let rest = file.strip_prefix(path)?;
+            Some(match rest.split_once('/') {
+                Some((dir, _)) => format!("{path}{dir}/"),
+                None => file.clone(),
+            })
+        })
+        .collect();
+    children.dedup();
+    if children.is_empty() {
+        return None;
+    }
+    let entries: Vec<String> = children
+        .iter()
+        .map(|child| {
+            format!(r#"{{"last_modification":"2024-03-01T10:00:00+00:00","path":"{child}"}}"#)
+        })
+        .collect();
+    Some(format!("[{}]", entries.join(",")))
+}
+
+/// The file type of symlinks in a unix mode, the content of such entries being their target
+pub const SYMLINK: u32 = 0o120000;
+
+/// A zip archive of `(path, mode, content)` entries
+pub fn zip(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (path, mode, content) in entries {
+        let options = FileOptions::default().unix_permissions(*mode);
+        if mode & SYMLINK == SYMLINK {
+            let target = String::from_utf8(content.to_vec()).unwrap();
+            zip.add_symlink(*path, target, options).unwrap();
+            continue;
+        }
+        zip.start_file(*path, options).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+/// When every entry of [`tarball`] has been modified, 2024-03-01T10:00:00Z
+pub const MTIME: u64 = 1_709_287_200;
+
+/// A tarball of `(path, mode, content)` entries compressed as `ext`, either `.tar.gz` or `.tar.zst`
+pub fn tarball(ext: &str, entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+    let mut tar = tar::Builder::new(Vec::new());
+    for (path, mode, content) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(mode & 0o7777);
+        header.set_mtime(MTIME);
+        if mode & SYMLINK == SYMLINK {
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            let target = std::str::from_utf8(content).unwrap();
+            tar.append_link(&mut header, path, target).unwrap();
+            continue;
+        }
+        header.set_size(content.len() as u64);
+        tar.append_data(&mut header, path, *content).unwrap();
+    }
+    let tar = tar.into_inner().unwrap();
+    match ext {
+        ".tar.gz" => {
+            let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            gz.write_all(&tar).unwrap();
+            gz.finish().unwrap()
+        }
+        ".tar.zst" => zstd::encode_all(&tar[..], 0).unwrap(),
+        _ => panic!("{ext} is not a tarball"),
+    }
+}
+
+/// The core archive of `version`, its `bin/greycat` printing the version
+pub fn core_zip(version: &str) -> Vec<u8> {
+    let greycat = format!("#!/bin/sh\necho '{version} ({})'\n", gcm::get_arch());
+    zip(&[
+        ("bin/greycat", 0o755, greycat.as_bytes()),
+        ("lib/libgreycat.txt", 0o644, version.as_bytes()),
+    ])
+}
+
+/// A directory removed once dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "gcm-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+/// Tells whether archives are downloaded to the cache of the installation in `home`, rather than to the one of
+/// whoever runs the tests
+pub struct DownloadsWatcher {
+    downloads: PathBuf,
+    spooled: Arc<AtomicBool>,
+}
+
+impl DownloadsWatcher {
+    /// The watcher, along with whether an archive has been seen in `home/cache/downloads/` while downloading
+    pub fn new(home: &Path) -> (Self, Arc<AtomicBool>) {
+        let spooled = Arc::new(AtomicBool::new(false));
+        let watcher = Self {
+            downloads: home.join("cache/downloads"),
+            spooled: spooled.clone(),
+        };
+        (watcher, spooled)
+    }
+}
+
+impl gcm::Observer for DownloadsWatcher {
+    fn download_progress(&self, _: &gcm::Package, _: u64, _: Option<u64>) {
+        if std::fs::read_dir(&self.downloads).is_ok_and(|mut archives| archives.next().is_some()) {
+            self.spooled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Runs the gcm binary against `registry`, with `home` as `$GREYCAT_HOME` and nothing of the environment leaking in
+pub fn gcm(registry: &MockRegistry, home: &Path, args: &[&str]) -> Output {
+    gcm_with(
+        Path::new(env!("CARGO_BIN_EXE_gcm")),
+        registry,
+        home,
+        args,
+        &[],
+    )
+}
+
+/// Runs `exe` as [`gcm`] runs the gcm binary, with `env` set on top, eg. `GCM_NO_UPDATE_CHECK=` to check for updates
+pub fn gcm_with(
+    exe: &Path,
+    registry: &MockRegistry,
+    home: &Path,
+    args: &[&str],
+    env: &[(&str, &str)],
+) -> Output {
+    Command::new(exe)
+        .args(args)
+        .current_dir(home)
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("HOME", home)
+        .env("GREYCAT_HOME", home)
+        .env("GCM_REGISTRY", registry.url())
+        .env("GCM_NO_UPDATE_CHECK", "1")
+        .env("NO_COLOR", "1")
+        .envs(env.iter().copied())
+        .output()
+        .unwrap()
+}
+
+/// The packages installed in `home`
+pub fn installed(home: &Path) -> Vec<gcm::InstalledPackage> {
+    gcm::Installation::new(home).list().unwrap()
+}
+
+/// The package `name` installed in `home`, which must be there
+pub fn installed_package(home: &Path, name: &str) -> gcm::InstalledPackage {
+    installed(home)
+        .into_iter()
+        .find(|package| package.name == name)
+        .unwrap_or_else(|| panic!("{name} is not installed"))
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
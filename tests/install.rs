@@ -0,0 +1,793 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, UNIX_EPOCH};
+
+use common::{
+    DownloadsWatcher, MTIME, MockRegistry, SYMLINK, TempDir, core_zip, gcm, gcm_with, installed,
+    installed_package, stderr, stdout, tarball, unreachable_url, zip,
+};
+use gcm::{ErrorKind, Installation, Package, Registry, Target, get_arch};
+
+/// A registry with core 7.0.0 and 7.0.1 on stable, the latest being `latest`
+fn registry(latest: &str) -> MockRegistry {
+    let registry = MockRegistry::start();
+    for version in ["7.0.0-stable", "7.0.1-stable"] {
+        registry.publish(
+            "core",
+            Some(&get_arch()),
+            version,
+            ".zip",
+            core_zip(version),
+        );
+    }
+    registry.latest("core", latest);
+    registry
+}
+
+#[test]
+fn install_latest() {
+    let registry = registry("7.0.1-stable");
+    registry.publish(
+        "lang",
+        Some("noarch"),
+        "7.0.1-stable",
+        ".zip",
+        zip(&[("lib/std/core.gcl", 0o644, b"")]),
+    );
+    registry.latest("lang", "7.0.1-stable");
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let packages = installed(home.path());
+    let installed: Vec<(&str, &str)> = packages
+        .iter()
+        .map(|p| (p.name.as_str(), p.version.as_str()))
+        .collect();
+    // explorer is optional, and not published
+    assert_eq!(
+        installed,
+        [("core", "7.0.1-stable"), ("lang", "7.0.1-stable")]
+    );
+    assert_eq!(
+        fs::read_to_string(home.path().join("lib/libgreycat.txt")).unwrap(),
+        "7.0.1-stable"
+    );
+    assert!(home.path().join("lib/std/core.gcl").is_file());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(home.path().join("bin/greycat"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+}
+
+#[test]
+fn install_specific_version() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["install", "core", "7.0.0-stable"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let core = installed_package(home.path(), "core");
+    assert_eq!(core.version, "7.0.0-stable");
+    assert_eq!(core.pin.as_ref().unwrap().to_string(), "=7.0.0-stable");
+}
+
+#[test]
+fn update_to_latest() {
+    let registry = registry("7.0.0-stable");
+    // only in 7.0.0, removed by the update
+    registry.publish(
+        "core",
+        Some(&get_arch()),
+        "7.0.0-stable",
+        ".zip",
+        zip(&[
+            ("bin/greycat", 0o755, b"#!/bin/sh\n"),
+            ("lib/libgreycat.txt", 0o644, b"7.0.0-stable"),
+            ("lib/old.txt", 0o644, b"old"),
+        ]),
+    );
+    let home = TempDir::new();
+    let output = gcm(&registry, home.path(), &["install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(home.path().join("lib/old.txt").is_file());
+
+    let output = gcm(&registry, home.path(), &["update"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("everything is up to date"));
+
+    registry.latest("core", "7.0.1-stable");
+    let output = gcm(&registry, home.path(), &["update"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("7.0.0-stable -> 7.0.1-stable"),
+        "{}",
+        stdout(&output)
+    );
+
+    assert_eq!(
+        installed_package(home.path(), "core").version,
+        "7.0.1-stable"
+    );
+    assert_eq!(
+        fs::read_to_string(home.path().join("lib/libgreycat.txt")).unwrap(),
+        "7.0.1-stable"
+    );
+    assert!(!home.path().join("lib/old.txt").exists());
+}
+
+#[test]
+fn update_keeps_pinned_version() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+    let output = gcm(&registry, home.path(), &["install", "core", "7.0.0-stable"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = gcm(&registry, home.path(), &["update"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    assert_eq!(
+        installed_package(home.path(), "core").version,
+        "7.0.0-stable"
+    );
+}
+
+/// Overwriting the executable of a running process fails with `ETXTBSY`, gcm must replace it instead.
+#[cfg(target_os = "linux")]
+#[test]
+fn outdated_honours_the_pin() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["install", "core", "7.0.0-stable"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = gcm(&registry, home.path(), &["outdated", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let statuses: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(statuses[0]["allowed"], "7.0.0-stable");
+    assert_eq!(statuses[0]["latest_on_branch"], "7.0.1-stable");
+    assert_eq!(statuses[0]["outdated"], false);
+
+    // the same version, installed from its branch
+    registry.latest("core", "7.0.0-stable");
+    let output = gcm(&registry, home.path(), &["install", "core", "stable"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    registry.latest("core", "7.0.1-stable");
+    let output = gcm(&registry, home.path(), &["outdated"]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("gcm update"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn update_replaces_busy_executable() {
+    use std::process::Command;
+
+    let registry = registry("7.0.0-stable");
+    let sleep = fs::read("/bin/sleep").unwrap();
+    for version in ["7.0.0-stable", "7.0.1-stable"] {
+        let archive = zip(&[
+            ("bin/greycat", 0o755, &sleep),
+            ("lib/libgreycat.txt", 0o644, version.as_bytes()),
+        ]);
+        registry.publish("core", Some(&get_arch()), version, ".zip", archive);
+    }
+    let home = TempDir::new();
+    let output = gcm(&registry, home.path(), &["install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let mut running = Command::new(home.path().join("bin/greycat"))
+        .arg("30")
+        .spawn()
+        .unwrap();
+    registry.latest("core", "7.0.1-stable");
+    let output = gcm(&registry, home.path(), &["update"]);
+    let still_running = running.try_wait().unwrap().is_none();
+    running.kill().ok();
+    running.wait().ok();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(still_running);
+    assert_eq!(fs::read(home.path().join("bin/greycat")).unwrap(), sleep);
+    assert_eq!(
+        installed_package(home.path(), "core").version,
+        "7.0.1-stable"
+    );
+}
+
+#[test]
+fn uninstall_everything() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+    let output = gcm(&registry, home.path(), &["install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = gcm(&registry, home.path(), &["uninstall"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!home.path().join("bin/greycat").exists());
+    assert!(!home.path().join("lib/libgreycat.txt").exists());
+    assert!(!home.path().join("receipt.json").exists());
+}
+
+#[test]
+fn list_through_the_cli() {
+    let registry = registry("7.0.1-stable");
+    registry.publish("sdk/web", None, "7.0.1-stable", ".zip", zip(&[]));
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "core\nsdk/web\n");
+
+    let output = gcm(&registry, home.path(), &["list", "core", "stable"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("7.0.0-stable"));
+    assert!(stdout(&output).contains("7.0.1-stable"));
+}
+
+#[test]
+fn core_not_found() {
+    let registry = MockRegistry::start();
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["install"]);
+    assert!(stderr(&output).contains("not found"), "{}", stderr(&output));
+    assert!(installed(home.path()).is_empty());
+}
+
+#[test]
+fn unknown_version_fails() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["install", "core", "6.0.0-stable"]);
+    assert!(stderr(&output).contains("not found"), "{}", stderr(&output));
+    assert!(!home.path().join("bin/greycat").exists());
+}
+
+#[test]
+fn corrupted_archive_fails() {
+    let registry = registry("7.0.1-stable");
+    registry.publish(
+        "core",
+        Some(&get_arch()),
+        "7.0.1-stable",
+        ".zip",
+        b"not a zip".to_vec(),
+    );
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["install", "core", "stable"]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("reading package archive"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!home.path().join("bin").exists());
+}
+
+#[test]
+fn corrupted_archive_keeps_legacy_installation() {
+    let registry = registry("7.0.1-stable");
+    registry.publish(
+        "core",
+        Some(&get_arch()),
+        "7.0.1-stable",
+        ".zip",
+        b"not a zip".to_vec(),
+    );
+    // installed before receipts existed
+    let home = TempDir::new();
+    fs::create_dir_all(home.path().join("bin")).unwrap();
+    fs::create_dir_all(home.path().join("lib")).unwrap();
+    fs::write(home.path().join("bin/greycat"), "legacy").unwrap();
+    fs::write(home.path().join("lib/libgreycat.txt"), "6.10.0-stable").unwrap();
+
+    let output = gcm(&registry, home.path(), &["install", "core", "stable"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        fs::read_to_string(home.path().join("bin/greycat")).unwrap(),
+        "legacy"
+    );
+    assert_eq!(
+        fs::read_to_string(home.path().join("lib/libgreycat.txt")).unwrap(),
+        "6.10.0-stable"
+    );
+}
+
+#[test]
+fn legacy_installation_is_replaced() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+    fs::create_dir_all(home.path().join("lib")).unwrap();
+    fs::write(home.path().join("lib/ghost.txt"), "6.10.0-stable").unwrap();
+
+    let output = gcm(&registry, home.path(), &["install", "core", "stable"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!home.path().join("lib/ghost.txt").exists());
+    assert_eq!(
+        fs::read_to_string(home.path().join("lib/libgreycat.txt")).unwrap(),
+        "7.0.1-stable"
+    );
+}
+
+#[test]
+fn unsafe_archive_is_rejected() {
+    let registry = registry("7.0.1-stable");
+    let archive = zip(&[
+        ("bin/greycat", 0o755, b"#!/bin/sh\n"),
+        ("../escaped.txt", 0o644, b"outside"),
+    ]);
+    registry.publish("core", Some(&get_arch()), "7.0.1-stable", ".zip", archive);
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["install", "core", "stable"]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("invalid file path"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!home.path().join("bin/greycat").exists());
+    assert!(!home.path().parent().unwrap().join("escaped.txt").exists());
+}
+
+/// A `..` going back through a symlink pointing to a shallower directory
+#[test]
+fn symlink_traversal_is_rejected() {
+    let registry = registry("7.0.1-stable");
+    let archive = zip(&[
+        ("d/l", SYMLINK | 0o777, b".."),
+        ("d/l/../pwned.txt", 0o644, b"outside"),
+    ]);
+    registry.publish("core", Some(&get_arch()), "7.0.1-stable", ".zip", archive);
+    let tmp = TempDir::new();
+    let home = tmp.path().join("greycat");
+    fs::create_dir(&home).unwrap();
+
+    let output = gcm(&registry, &home, &["install", "core", "stable"]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("invalid file path"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!tmp.path().join("pwned.txt").exists());
+    assert!(!home.join("d").exists());
+}
+
+#[test]
+fn modified_files_survive_uninstall() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+    let output = gcm(&registry, home.path(), &["install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    fs::write(home.path().join("lib/libgreycat.txt"), "mine").unwrap();
+
+    let output = gcm(&registry, home.path(), &["uninstall"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("was modified, kept"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(
+        fs::read_to_string(home.path().join("lib/libgreycat.txt")).unwrap(),
+        "mine"
+    );
+}
+
+#[test]
+fn added_files_are_backed_up_once_overwritten() {
+    let registry = registry("7.0.0-stable");
+    let home = TempDir::new();
+    let output = gcm(&registry, home.path(), &["install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    // shipped by 7.0.1 only
+    registry.publish(
+        "core",
+        Some(&get_arch()),
+        "7.0.1-stable",
+        ".zip",
+        zip(&[
+            ("bin/greycat", 0o755, b"#!/bin/sh\n"),
+            ("lib/extra.txt", 0o644, b"7.0.1-stable"),
+        ]),
+    );
+    registry.latest("core", "7.0.1-stable");
+    fs::write(home.path().join("lib/extra.txt"), "mine").unwrap();
+    fs::write(home.path().join("lib/extra.txt.gcm-bak"), "older").unwrap();
+
+    let output = gcm(&registry, home.path(), &["update"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("lib/extra.txt.gcm-bak.1"),
+        "{}",
+        stderr(&output)
+    );
+    let read = |file: &str| fs::read_to_string(home.path().join(file)).unwrap();
+    assert_eq!(read("lib/extra.txt"), "7.0.1-stable");
+    assert_eq!(read("lib/extra.txt.gcm-bak.1"), "mine");
+    assert_eq!(read("lib/extra.txt.gcm-bak"), "older");
+}
+
+#[test]
+fn installation_uses_its_registry() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+
+    let (watcher, spooled) = DownloadsWatcher::new(home.path());
+    let installation = Installation::new(home.path())
+        .with_registry(Registry::new(registry.url()))
+        .with_observer(watcher);
+    let installed = installation
+        .install(vec![Target::latest(Package::new(
+            "core",
+            Some(get_arch()),
+            "stable",
+        ))])
+        .unwrap();
+    assert_eq!(installed[0].version, "7.0.1-stable");
+    // downloaded to the cache of the installation, then removed
+    assert!(spooled.load(Ordering::Relaxed));
+    assert!(
+        fs::read_dir(home.path().join("cache/downloads"))
+            .unwrap()
+            .next()
+            .is_none()
+    );
+    assert_eq!(
+        fs::read_to_string(home.path().join("lib/libgreycat.txt")).unwrap(),
+        "7.0.1-stable"
+    );
+}
+
+#[test]
+fn installation_reads_its_own_config() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+    fs::write(
+        home.path().join("config.json"),
+        r#"{"extract_limits":{"max_entries":1}}"#,
+    )
+    .unwrap();
+
+    let err = Installation::new(home.path())
+        .with_registry(Registry::new(registry.url()))
+        .install(vec![Target::latest(Package::new(
+            "core",
+            Some(get_arch()),
+            "stable",
+        ))])
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidArchive);
+    assert!(
+        format!("{err:?}").contains("max_entries exceeded"),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn unreachable_registry_is_not_a_missing_package() {
+    let url = unreachable_url();
+    let home = TempDir::new();
+
+    let err = Installation::new(home.path())
+        .with_registry(Registry::new(url))
+        .install(Target::defaults("stable"))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Registry);
+}
+
+#[test]
+fn dry_run_changes_nothing() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["install", "--dry-run"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("install core@stable 7.0.1-stable"),
+        "{}",
+        stdout(&output)
+    );
+    assert!(stdout(&output).contains("write    bin/greycat"));
+    assert!(
+        stderr(&output).contains("lang@stable          not found"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!home.path().join("bin").exists());
+    assert!(!home.path().join("receipt.json").exists());
+}
+
+#[test]
+fn self_uninstall_removes_shims_and_init_shell() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+    let output = gcm(&registry, home.path(), &["install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(home.path().join("shims/greycat").is_file());
+    let bashrc = home.path().join(".bashrc");
+    fs::write(&bashrc, "alias ll='ls -l'\n").unwrap();
+    let output = gcm(&registry, home.path(), &["init-shell", "--shell", "bash"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // removing itself, gcm must not be the binary the other tests run
+    let exe = home.path().join("gcm");
+    fs::copy(env!("CARGO_BIN_EXE_gcm"), &exe).unwrap();
+    let output = gcm_with(&exe, &registry, home.path(), &["self", "uninstall"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!exe.exists());
+    assert!(!home.path().join("shims").exists());
+    assert_eq!(fs::read_to_string(&bashrc).unwrap(), "alias ll='ls -l'\n\n");
+    // the installation itself is only removed with --all
+    assert!(home.path().join("bin/greycat").is_file());
+}
+
+#[test]
+fn self_uninstall_all_asks_for_confirmation() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+    let output = gcm(&registry, home.path(), &["install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let exe = home.path().join("bin/gcm");
+    fs::copy(env!("CARGO_BIN_EXE_gcm"), &exe).unwrap();
+
+    // nobody to ask outside of a terminal
+    let args = ["self", "uninstall", "--all"];
+    let output = gcm_with(&exe, &registry, home.path(), &args, &[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("--yes"), "{}", stderr(&output));
+    assert!(home.path().join("bin/greycat").is_file());
+
+    // gcm itself lived in the installation directory
+    let args = ["self", "uninstall", "--all", "--yes"];
+    let output = gcm_with(&exe, &registry, home.path(), &args, &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!home.path().exists());
+}
+
+#[test]
+fn run_prints_the_update_hint() {
+    let registry = registry("7.0.0-stable");
+    let home = TempDir::new();
+    let output = gcm(&registry, home.path(), &["install", "core", "stable"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    fs::create_dir_all(home.path().join("cache")).unwrap();
+    fs::write(
+        home.path().join("cache/update-check.json"),
+        format!(
+            r#"{{"updated_at":"{}","value":[{{"name":"core","branch":"stable","version":"7.0.1-stable"}}]}}"#,
+            chrono::Local::now().to_rfc3339()
+        ),
+    )
+    .unwrap();
+
+    let run = || {
+        gcm_with(
+            Path::new(env!("CARGO_BIN_EXE_gcm")),
+            &registry,
+            home.path(),
+            &["run", "--", "greycat"],
+            &[("GCM_NO_UPDATE_CHECK", "")],
+        )
+    };
+
+    let output = run();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).starts_with("7.0.0-stable"),
+        "{}",
+        stdout(&output)
+    );
+    assert!(
+        stderr(&output).contains("core@stable 7.0.1-stable is available"),
+        "{}",
+        stderr(&output)
+    );
+
+    // 'gcm update' would not move a pinned version
+    let output = gcm(&registry, home.path(), &["install", "core", "7.0.0-stable"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = run();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        !stderr(&output).contains("is available"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn failed_upgrade_keeps_the_previous_version() {
+    let registry = registry("7.0.0-stable");
+    let home = TempDir::new();
+    let output = gcm(&registry, home.path(), &["install"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    // only found out once the first files of 7.0.1 have been moved in place
+    fs::create_dir_all(home.path().join("share")).unwrap();
+    fs::write(home.path().join("share/notes.txt"), "mine").unwrap();
+    registry.publish(
+        "core",
+        Some(&get_arch()),
+        "7.0.1-stable",
+        ".zip",
+        zip(&[
+            ("bin/greycat", 0o755, b"#!/bin/sh\necho broken\n"),
+            ("lib/libgreycat.txt", 0o644, b"7.0.1-stable"),
+            ("share", 0o644, b"not a directory"),
+        ]),
+    );
+    registry.latest("core", "7.0.1-stable");
+
+    let output = gcm(&registry, home.path(), &["update"]);
+    assert!(!output.status.success());
+    let read = |file: &str| fs::read_to_string(home.path().join(file)).unwrap();
+    assert_eq!(
+        read("bin/greycat"),
+        format!("#!/bin/sh\necho '7.0.0-stable ({})'\n", get_arch())
+    );
+    assert_eq!(read("lib/libgreycat.txt"), "7.0.0-stable");
+    assert_eq!(read("share/notes.txt"), "mine");
+    assert_eq!(
+        installed_package(home.path(), "core").version,
+        "7.0.0-stable"
+    );
+    // nor is the staging directory left behind
+    let leftovers: Vec<_> = fs::read_dir(home.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name.to_string_lossy().starts_with(".gcm-staging"))
+        .collect();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+}
+
+#[test]
+fn escaping_symlink_is_rejected() {
+    let registry = registry("7.0.1-stable");
+    let archive = zip(&[
+        ("bin/greycat", 0o755, b"#!/bin/sh\n"),
+        ("lib/outside", SYMLINK | 0o777, b"../../etc"),
+    ]);
+    registry.publish("core", Some(&get_arch()), "7.0.1-stable", ".zip", archive);
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["install", "core", "stable"]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("symlink pointing outside of the installation directory"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!home.path().join("bin/greycat").exists());
+    assert!(fs::symlink_metadata(home.path().join("lib/outside")).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn enclosed_symlinks_are_extracted() {
+    let registry = registry("7.0.1-stable");
+    let archive = zip(&[
+        ("bin/greycat", 0o755, b"#!/bin/sh\n"),
+        ("lib/libgreycat.txt", 0o644, b"7.0.1-stable"),
+        ("lib/current", SYMLINK | 0o777, b"libgreycat.txt"),
+        ("bin/lib", SYMLINK | 0o777, b"../lib"),
+    ]);
+    registry.publish("core", Some(&get_arch()), "7.0.1-stable", ".zip", archive);
+    let home = TempDir::new();
+
+    let output = gcm(&registry, home.path(), &["install", "core", "stable"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        fs::read_link(home.path().join("lib/current")).unwrap(),
+        std::path::Path::new("libgreycat.txt")
+    );
+    assert_eq!(
+        fs::read_to_string(home.path().join("bin/lib/current")).unwrap(),
+        "7.0.1-stable"
+    );
+}
+
+#[test]
+fn extraction_limits_are_enforced() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+    fs::write(
+        home.path().join("config.json"),
+        r#"{"extract_limits":{"max_file_bytes":4}}"#,
+    )
+    .unwrap();
+
+    let output = gcm(&registry, home.path(), &["install", "core", "stable"]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("max_file_bytes exceeded"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!home.path().join("bin/greycat").exists());
+    assert!(!home.path().join("receipt.json").exists());
+}
+
+#[test]
+fn oversized_download_is_refused() {
+    let registry = registry("7.0.1-stable");
+    let home = TempDir::new();
+    // the archive is smaller than what it extracts, but still over the limit
+    fs::write(
+        home.path().join("config.json"),
+        r#"{"extract_limits":{"max_total_bytes":100}}"#,
+    )
+    .unwrap();
+
+    for args in [
+        &["install", "core", "stable"][..],
+        &["install", "core", "stable", "--dry-run"],
+    ] {
+        let output = gcm(&registry, home.path(), args);
+        assert!(!output.status.success());
+        assert!(
+            stderr(&output).contains("max_total_bytes exceeded"),
+            "{}",
+            stderr(&output)
+        );
+    }
+    assert!(!home.path().join("bin/greycat").exists());
+    assert!(
+        !fs::read_dir(home.path().join("cache/downloads"))
+            .is_ok_and(|mut archives| archives.next().is_some())
+    );
+}
+
+#[test]
+fn install_tarballs() {
+    for ext in [".tar.zst", ".tar.gz"] {
+        let registry = MockRegistry::start();
+        let archive = tarball(
+            ext,
+            &[
+                ("bin/greycat", 0o755, b"#!/bin/sh\necho tarball\n"),
+                ("lib/libgreycat.txt", 0o644, b"7.0.1-stable"),
+                ("lib/current", SYMLINK | 0o777, b"libgreycat.txt"),
+            ],
+        );
+        registry.publish("core", Some(&get_arch()), "7.0.1-stable", ext, archive);
+        registry.latest("core", "7.0.1-stable");
+        let home = TempDir::new();
+
+        let output = gcm(&registry, home.path(), &["install", "core", "stable"]);
+        assert!(output.status.success(), "{ext}: {}", stderr(&output));
+        let lib = home.path().join("lib/libgreycat.txt");
+        assert_eq!(fs::read_to_string(&lib).unwrap(), "7.0.1-stable");
+        assert_eq!(
+            fs::metadata(&lib).unwrap().modified().unwrap(),
+            UNIX_EPOCH + Duration::from_secs(MTIME),
+            "{ext}"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let greycat = fs::metadata(home.path().join("bin/greycat")).unwrap();
+            assert_eq!(greycat.permissions().mode() & 0o777, 0o755, "{ext}");
+            assert_eq!(
+                fs::read_link(home.path().join("lib/current")).unwrap(),
+                std::path::Path::new("libgreycat.txt")
+            );
+        }
+    }
+}
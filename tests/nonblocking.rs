@@ -0,0 +1,103 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use std::fs;
+use std::sync::atomic::Ordering;
+
+use common::{DownloadsWatcher, MockRegistry, TempDir, core_zip, unreachable_url};
+use gcm::nonblocking::{Installation, Registry};
+use gcm::{ErrorKind, Package, Target, get_arch};
+
+/// Runs `future` to completion on a single threaded runtime, extraction going to its blocking pool
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+fn core() -> Target {
+    Target::latest(Package::new("core", Some(get_arch()), "stable"))
+}
+
+#[test]
+fn install_latest() {
+    let registry = MockRegistry::start();
+    for version in ["7.0.0-stable", "7.0.1-stable"] {
+        registry.publish(
+            "core",
+            Some(&get_arch()),
+            version,
+            ".zip",
+            core_zip(version),
+        );
+    }
+    registry.latest("core", "7.0.1-stable");
+    let home = TempDir::new();
+
+    let (watcher, spooled) = DownloadsWatcher::new(home.path());
+    let installation = Installation::new(home.path())
+        .with_registry(Registry::new(registry.url()))
+        .with_observer(watcher);
+    let installed = block_on(installation.install(vec![core()])).unwrap();
+    assert_eq!(installed.len(), 1);
+    assert!(spooled.load(Ordering::Relaxed));
+    assert_eq!(installed[0].version, "7.0.1-stable");
+    assert_eq!(
+        fs::read_to_string(home.path().join("lib/libgreycat.txt")).unwrap(),
+        "7.0.1-stable"
+    );
+
+    let versions =
+        block_on(Registry::new(registry.url()).list_package_versions("core", "stable", None))
+            .unwrap();
+    assert_eq!(versions.len(), 2);
+}
+
+#[test]
+fn missing_archive_is_skipped() {
+    let registry = MockRegistry::start();
+    // the latest version points to an archive that is not published
+    registry.latest("core", "7.0.1-stable");
+    let home = TempDir::new();
+
+    let installation = Installation::new(home.path()).with_registry(Registry::new(registry.url()));
+    let installed = block_on(installation.install(vec![core()])).unwrap();
+    assert!(installed.is_empty());
+}
+
+#[test]
+fn oversized_download_is_refused() {
+    let registry = MockRegistry::start();
+    registry.publish(
+        "core",
+        Some(&get_arch()),
+        "7.0.1-stable",
+        ".zip",
+        core_zip("7.0.1-stable"),
+    );
+    registry.latest("core", "7.0.1-stable");
+    let home = TempDir::new();
+    fs::write(
+        home.path().join("config.json"),
+        r#"{"extract_limits":{"max_total_bytes":100}}"#,
+    )
+    .unwrap();
+
+    let installation = Installation::new(home.path()).with_registry(Registry::new(registry.url()));
+    let err = block_on(installation.install(vec![core()])).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidArchive);
+    assert!(!home.path().join("bin/greycat").exists());
+}
+
+#[test]
+fn unreachable_registry_fails() {
+    let url = unreachable_url();
+    let home = TempDir::new();
+
+    let installation = Installation::new(home.path()).with_registry(Registry::new(url));
+    let err = block_on(installation.install(vec![core()])).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Registry);
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use common::{MockRegistry, unreachable_url, zip};
+use gcm::{ErrorKind, Registry};
+
+fn versions(
+    registry: &MockRegistry,
+    name: &str,
+    branch: &str,
+    limit: Option<usize>,
+) -> Vec<String> {
+    Registry::new(registry.url())
+        .list_package_versions(name, branch, limit)
+        .unwrap()
+        .into_iter()
+        .map(|version| version.version.to_string())
+        .collect()
+}
+
+#[test]
+fn list_packages() {
+    let registry = MockRegistry::start();
+    let archive = zip(&[("a.txt", 0o644, b"a")]);
+    registry.publish(
+        "core",
+        Some("x64-linux"),
+        "7.0.1-stable",
+        ".zip",
+        archive.clone(),
+    );
+    registry.publish(
+        "lang",
+        Some("noarch"),
+        "7.0.1-stable",
+        ".zip",
+        archive.clone(),
+    );
+    registry.publish("sdk/web", None, "7.0.1-stable", ".zip", archive.clone());
+    registry.publish("sdk/python", None, "7.0.1-stable", ".zip", archive.clone());
+    registry.file("deps/libfoo.so", "ignored");
+
+    let packages: Vec<String> = Registry::new(registry.url())
+        .list_packages()
+        .unwrap()
+        .into_iter()
+        .map(|file| file.path)
+        .collect();
+    assert_eq!(packages, ["core/", "lang/", "sdk/python/", "sdk/web/"]);
+}
+
+#[test]
+fn list_package_versions_in_arch_directories() {
+    let registry = MockRegistry::start();
+    for version in ["6.10.5-stable", "7.0.0-stable", "7.0.1-stable"] {
+        let archive = zip(&[("a.txt", 0o644, version.as_bytes())]);
+        registry.publish("core", Some("x64-linux"), version, ".zip", archive.clone());
+        registry.publish("core", Some("arm64-linux"), version, ".zip", archive);
+    }
+    registry.publish("core", Some("x64-linux"), "7.1.0-dev", ".zip", zip(&[]));
+
+    assert_eq!(
+        versions(&registry, "core", "stable", None),
+        ["6.10.5-stable", "7.0.0-stable", "7.0.1-stable"]
+    );
+    assert_eq!(versions(&registry, "core", "dev", None), ["7.1.0-dev"]);
+}
+
+#[test]
+fn list_package_versions_without_arch() {
+    let registry = MockRegistry::start();
+    registry.publish("sdk/web", None, "7.0.0-stable", ".zip", zip(&[]));
+    registry.publish("sdk/web", None, "7.0.1-stable", ".zip", zip(&[]));
+    // published in several formats, listed once
+    registry.publish("sdk/web", None, "7.0.1-stable", ".tar.gz", Vec::new());
+    registry.file("sdk/web/stable/7.0/checksums.txt", "");
+
+    assert_eq!(
+        versions(&registry, "sdk/web", "stable", None),
+        ["7.0.0-stable", "7.0.1-stable"]
+    );
+}
+
+#[test]
+fn list_package_versions_keeps_the_latest() {
+    let registry = MockRegistry::start();
+    for version in [
+        "7.0.0-stable",
+        "7.0.1-stable",
+        "7.1.0-stable",
+        "7.1.1-stable",
+    ] {
+        registry.publish("core", Some("x64-linux"), version, ".zip", zip(&[]));
+    }
+
+    assert_eq!(
+        versions(&registry, "core", "stable", Some(2)),
+        ["7.1.0-stable", "7.1.1-stable"]
+    );
+}
+
+#[test]
+fn list_package_branches() {
+    let registry = MockRegistry::start();
+    registry.publish("core", Some("x64-linux"), "7.0.1-stable", ".zip", zip(&[]));
+    registry.publish("core", Some("x64-linux"), "7.1.0-dev", ".zip", zip(&[]));
+
+    let branches: Vec<String> = Registry::new(registry.url())
+        .list_package_branches("core")
+        .unwrap()
+        .into_iter()
+        .map(|file| file.path)
+        .collect();
+    assert_eq!(branches, ["core/dev/", "core/stable/"]);
+}
+
+#[test]
+fn unknown_package_is_not_found() {
+    let registry = MockRegistry::start();
+    registry.publish("core", Some("x64-linux"), "7.0.1-stable", ".zip", zip(&[]));
+
+    let err = Registry::new(registry.url())
+        .list_package_versions("nope", "stable", None)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert_eq!(err.to_string(), "no version found for \"nope/stable\"");
+}
+
+#[test]
+fn unreachable_registry() {
+    let url = unreachable_url();
+
+    let err = Registry::new(url).list_packages().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Registry);
+}